@@ -22,6 +22,9 @@ class ArticleEditor {
         let title_input_timeout = null;
         this.title_input = document.querySelector(".article-title");
 
+        // Optional article fields, named by their data-article-field attribute
+        this.field_inputs = document.querySelectorAll("[data-article-field]");
+
         // Article management
         this.publish_button = document.querySelector("#publish-article");
         this.unpublish_button = document.querySelector("#unpublish-article");
@@ -35,6 +38,9 @@ class ArticleEditor {
         // Editor status
         this.editor_status = document.querySelector("#editor-status");
         this.editor_status_last_update = document.querySelector("#editor-status-last-update");
+        // Validation errors of the last save, the draft isn't saved while there are some
+        this.errors_list = document.querySelector("#article-errors");
+        this.has_errors = false;
 
         setInterval(() => {
             this.update_article_status();
@@ -52,6 +58,13 @@ class ArticleEditor {
 
             // Set article title
             this.title_input.value = article.title;
+            this.field_inputs.forEach((input) => {
+                if (input.type == "checkbox") {
+                    input.checked = article[input.dataset.articleField] == true;
+                } else {
+                    input.value = article[input.dataset.articleField] || "";
+                }
+            });
             this.status = response.status;
             this.is_published = response.published;
             this.published_date = response.published_date;
@@ -148,6 +161,15 @@ class ArticleEditor {
                     this.save_article();
                 }, 500);
             })
+
+            this.field_inputs.forEach((input) => {
+                input.addEventListener("input", () => {
+                    clearTimeout(this.title_input_timeout);
+                    this.title_input_timeout = setTimeout(() => {
+                        this.save_article();
+                    }, 500);
+                })
+            });
     
             this.publish_button.addEventListener("click", () => {
                 // Publish the article
@@ -158,7 +180,7 @@ class ArticleEditor {
                     this.status = "published";
                     this.update_article_status();
                     this.update_editor_status();
-                }).catch(() => {})
+                }).catch((error) => alert(error))
             })
 
             this.unpublish_button.addEventListener("click", () => {
@@ -169,21 +191,21 @@ class ArticleEditor {
                     this.status = "draft";
                     this.update_article_status();
                     this.update_editor_status();
-                }).catch(() => {})
+                }).catch((error) => alert(error))
             })
 
             this.discard_changes_button.addEventListener("click", () => {
                 // Unpublish the article
                 this.discard_article_changes(this.article_id).then(() => {
                     location.reload();
-                }).catch(() => {})
+                }).catch((error) => alert(error))
             })
 
             this.delete_article_button.addEventListener("click", () => {
                 // Delete the article
                 this.delete_article(this.article_id).then(() => {
                     window.location = `${location.protocol}//${location.host}/dashboard/articles`
-                }).catch(() => {})
+                }).catch((error) => alert(error))
             })
         }).catch(() => {
             console.log("Not found")
@@ -228,6 +250,12 @@ class ArticleEditor {
     }
 
     update_editor_status () {
+        if (this.has_errors) {
+            this.editor_status.innerText = "Not Saved";
+            this.editor_status.setAttribute("status", "alert");
+            this.editor_status_last_update.innerText = "Last saved " + moment(this.update_date).fromNow();
+            return;
+        }
         switch (this.status.toLowerCase()) {
             case "published":
                 this.editor_status.innerText = "Not Modified";
@@ -247,6 +275,27 @@ class ArticleEditor {
         this.editor_status_last_update.innerText = "Last saved " + moment(this.update_date).fromNow();
     }
 
+    // Errors of the title and details are shown on their input, the ones of the blocks by position
+    show_errors (errors) {
+        this.has_errors = errors.length > 0;
+        this.errors_list.innerHTML = "";
+        [this.title_input, ...this.field_inputs].forEach((input) => input.removeAttribute("invalid"));
+        for (const error of errors) {
+            var block = error.field.match(/^data\.blocks\[(\d+)\]/);
+            var label = block ? `Block ${Number(block[1]) + 1}` : error.field.replace(/_/g, " ");
+            var input = error.field == "title"
+                ? this.title_input
+                : document.querySelector(`[data-article-field="${error.field}"]`);
+            if (input) {
+                input.setAttribute("invalid", "");
+            }
+            var item = document.createElement("li");
+            item.innerText = `${label}: ${error.message}`;
+            this.errors_list.appendChild(item);
+        }
+        this.update_editor_status();
+    }
+
     load_article (article_id) {
         return new Promise((resolve, reject) => {
             fetch(`${location.protocol}//${location.host}/dashboard/api/article/${article_id}`, {
@@ -279,6 +328,9 @@ class ArticleEditor {
             title,
             data: JSON.stringify(data)
         };
+        this.field_inputs.forEach((input) => {
+            draft[input.dataset.articleField] = input.type == "checkbox" ? input.checked : input.value;
+        });
     
         return new Promise((resolve, reject) => {
             fetch(`${location.protocol}//${location.host}/dashboard/api/article/${article_id}`, {
@@ -295,9 +347,17 @@ class ArticleEditor {
             }).then((response) => {
                 if (response.status == 200) {
                     console.log("Draft saved");
+                    this.show_errors([]);
                     resolve();
+                } else if (response.status == 422) {
+                    response.json().then((problem) => {
+                        this.show_errors(problem.errors);
+                    }).catch(() => {});
+                    reject();
                 } else {
-                    console.log("Draft not saved saved: ", response.text());
+                    response.json().then((problem) => {
+                        console.log("Draft not saved: ", problem.detail);
+                    }).catch(() => {});
                     reject();
                 }
             }).catch((err) => {
@@ -339,7 +399,9 @@ class ArticleEditor {
                 if (response.status == 200) {
                     resolve("Published");
                 } else {
-                    reject(`Error on ${action} the article`);
+                    response.json()
+                        .then((problem) => reject(problem.detail))
+                        .catch(() => reject(`Error on ${action} the article`));
                 }
             }).catch((err) => {
                 reject(`Error ${action} the article`);
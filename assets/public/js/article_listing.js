@@ -11,12 +11,17 @@ class ArticlesExplorer {
             page: 0,
             count: 30,
             total: 0,
-            max_buttons: 9
+            max_buttons: 9,
+            // Cursor to request each visited page, the first page has none
+            cursors: [null]
         }
 
         // Articles Explorer root element
         this.container = document.querySelector(container_selector);
 
+        // Sort select, its values are "{field}:{order}"
+        this.sort_select = this.container.querySelector(".article-sort-select");
+
         // Paginator search box input
         this.search_box = this.container.querySelector(".article-search-box");
         
@@ -46,6 +51,32 @@ class ArticlesExplorer {
             this.prev_page();
         })
 
+        this.paginator_pages_box.addEventListener("click", (event) => {
+            var page = event.target.dataset.page;
+            if (page !== undefined) {
+                this.paginator.page = parseInt(page);
+                this.load_page();
+            }
+        })
+
+        // Cursors depend on the order, start again from the first page
+        this.sort_select.addEventListener("change", () => {
+            this.paginator.page = 0;
+            this.paginator.cursors = [null];
+            this.load_page();
+        })
+
+        // Filter by title while typing, waiting a bit between keystrokes
+        this.search_timeout = null;
+        this.search_box.addEventListener("input", () => {
+            clearTimeout(this.search_timeout);
+            this.search_timeout = setTimeout(() => {
+                this.paginator.page = 0;
+                this.paginator.cursors = [null];
+                this.load_page();
+            }, 300);
+        })
+
         // Load the first page
         this.load_page ();
     }
@@ -64,7 +95,16 @@ class ArticlesExplorer {
     }
 
     load_page () {
-        this.get_article_list(this.type, this.paginator.count * this.paginator.page, this.paginator.count).then((response) => {
+        // Continue from the closest known page before this one, skipping the pages in between
+        var known = this.paginator.page;
+        while (known > 0 && !this.paginator.cursors[known]) {
+            known -= 1;
+        }
+        var skip = this.paginator.count * (this.paginator.page - known);
+        this.get_article_list(this.type, skip, this.paginator.count, this.paginator.cursors[known]).then((response) => {
+            // Remember where the next page starts so new articles don't shift it
+            this.paginator.cursors[this.paginator.page + 1] = response.next_cursor;
+
             // Calc the total pages
             var total_pages = Math.ceil(response.total / this.paginator.count);
 
@@ -72,7 +112,7 @@ class ArticlesExplorer {
             this.paginator.total = total_pages;
 
             // Fill info in to show the user
-            this.paginator_current_articles_count.innerText = Object.keys(response.articles).length;
+            this.paginator_current_articles_count.innerText = response.articles.length;
             this.paginator_total_articles_count.innerText = response.total;
             this.paginator_current_page.innerText = this.paginator.page + 1;
             this.paginator_total_pages.innerText = total_pages;
@@ -88,6 +128,7 @@ class ArticlesExplorer {
             for (let i = page_range.start; i < page_range.end; i++) {
                 var page_selector = document.createElement("button");
                 page_selector.innerText = i + 1;
+                page_selector.dataset.page = i;
                 if(i == this.paginator.page) {
                     page_selector.setAttribute("current", "");
                 }
@@ -104,14 +145,13 @@ class ArticlesExplorer {
     render_articles (articles) {
         // Clear the results box the dirty and easy way
         this.results_box.innerHTML = "";
-        for (const article_id in articles) {
-            const article = articles[article_id];
+        for (const article of articles) {
             // Create article element
             var article_link = document.createElement("a");
             var article_box = document.createElement("div");
             var article_title = document.createElement("div");
 
-            article_link.href = `${location.protocol}//${location.host}/dashboard/article/edit/${article_id}`
+            article_link.href = `${location.protocol}//${location.host}/dashboard/article/edit/${article.id}`
             article_title.innerText = article.title;
 
             article_link.classList.add("article-link");
@@ -123,7 +163,7 @@ class ArticlesExplorer {
             this.results_box.appendChild(article_link);
         }
         
-        if (Object.keys(articles).length == 0)
+        if (articles.length == 0)
         {
             this.results_box.innerHTML = `<div class="no-articles-found">No articles found</div>`;
         }
@@ -152,9 +192,21 @@ class ArticlesExplorer {
         };
     }
 
-    get_article_list (type, start, count) {
+    get_article_list (type, start, count, cursor) {
+        var sort = this.sort_select.value.split(":");
+        var query = new URLSearchParams({
+            status: type,
+            start_index: start,
+            count: count,
+            sort: sort[0],
+            order: sort[1],
+            title: this.search_box.value
+        });
+        if (cursor) {
+            query.set("after", cursor);
+        }
         return new Promise((resolve, reject) => {
-            fetch(`${location.protocol}//${location.host}/dashboard/api/articles/${type}/${start}/${count}`, {
+            fetch(`${location.protocol}//${location.host}/dashboard/api/articles/search?${query}`, {
                 method: 'GET',
                 mode: 'cors',
                 cache: 'no-cache',
@@ -0,0 +1,20 @@
+window.addEventListener("load", () => {
+    // Dates and sizes are rendered raw by the server
+    document.querySelectorAll("[data-date]").forEach((element) => {
+        element.title = element.dataset.date;
+        element.textContent = moment(element.dataset.date).fromNow();
+    })
+    document.querySelectorAll("[data-bytes]").forEach((element) => {
+        element.textContent = format_bytes(Number(element.dataset.bytes));
+    })
+})
+
+function format_bytes (bytes) {
+    const units = ["B", "KB", "MB", "GB", "TB"];
+    var unit = 0;
+    while (bytes >= 1024 && unit < units.length - 1) {
+        bytes /= 1024;
+        unit += 1;
+    }
+    return `${unit == 0 ? bytes : bytes.toFixed(1)} ${units[unit]}`;
+}
@@ -0,0 +1,220 @@
+window.addEventListener("load", () => {
+    var theme_settings = new ThemeSettings("#theme-settings");
+    // Only rendered for admins
+    if (document.querySelector("#site-settings .settings-form")) {
+        var site_settings = new SiteSettings("#site-settings");
+    }
+})
+
+// Message of an api error, answered as problem details json
+function problem_detail (response) {
+    return response.json()
+        .then((problem) => problem.detail)
+        .catch(() => response.statusText);
+}
+
+class ThemeSettings {
+    constructor (container_selector) {
+        this.container = document.querySelector(container_selector);
+        this.select = this.container.querySelector(".theme-select");
+        this.save_button = this.container.querySelector(".settings-save-button");
+        this.description = this.container.querySelector(".theme-description");
+        this.status = this.container.querySelector(".settings-status");
+        this.themes = [];
+
+        this.select.addEventListener("change", () => {
+            this.show_description();
+        })
+
+        this.save_button.addEventListener("click", () => {
+            this.save_theme();
+        })
+
+        this.load_themes();
+    }
+
+    load_themes () {
+        fetch(`${location.protocol}//${location.host}/dashboard/api/themes`, {
+            method: 'GET',
+            cache: 'no-cache',
+            credentials: 'same-origin'
+        }).then((response) => response.json()).then((response) => {
+            this.themes = response.themes;
+            this.select.innerHTML = "";
+            for (const theme of this.themes) {
+                var option = document.createElement("option");
+                option.value = theme.id;
+                option.innerText = `${theme.name} ${theme.version}`;
+                option.selected = theme.id == response.active;
+                this.select.appendChild(option);
+            }
+            this.show_description();
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+
+    show_description () {
+        var theme = this.themes.find((theme) => theme.id == this.select.value);
+        this.description.innerText = theme ? `${theme.description} - ${theme.author}` : "";
+    }
+
+    save_theme () {
+        fetch(`${location.protocol}//${location.host}/dashboard/api/theme`, {
+            method: 'PUT',
+            cache: 'no-cache',
+            credentials: 'same-origin',
+            headers: {
+                'Content-Type': 'application/json'
+            },
+            body: JSON.stringify({ theme: this.select.value })
+        }).then((response) => {
+            if (response.status == 200) {
+                this.status.removeAttribute("status");
+                this.status.innerText = "Theme saved";
+            } else {
+                problem_detail(response).then((detail) => {
+                    this.status.setAttribute("status", "error");
+                    this.status.innerText = detail;
+                })
+            }
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+}
+
+class SiteSettings {
+    constructor (container_selector) {
+        this.container = document.querySelector(container_selector);
+        this.form = this.container.querySelector(".settings-form");
+        this.social_links = this.container.querySelector(".social-links");
+        this.status = this.container.querySelector(".settings-status");
+
+        this.container.querySelector(".social-link-add-button").addEventListener("click", () => {
+            this.add_social_link({ name: "", url: "" });
+        })
+
+        this.form.addEventListener("submit", (event) => {
+            event.preventDefault();
+            this.save_settings();
+        })
+
+        this.load_settings();
+    }
+
+    load_settings () {
+        fetch(`${location.protocol}//${location.host}/dashboard/api/settings`, {
+            method: 'GET',
+            cache: 'no-cache',
+            credentials: 'same-origin'
+        }).then((response) => response.json()).then((settings) => {
+            this.show_settings(settings);
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+
+    show_settings (settings) {
+        for (const field of ["name", "description", "base_url", "default_author", "posts_per_page", "timezone", "date_format", "registration", "comments"]) {
+            this.form.elements[field].value = settings[field];
+        }
+        this.social_links.innerHTML = "";
+        for (const link of settings.social_links) {
+            this.add_social_link(link);
+        }
+    }
+
+    add_social_link (link) {
+        var row = document.createElement("div");
+        row.className = "social-link";
+        var name = document.createElement("input");
+        name.className = "social-link-name";
+        name.placeholder = "Name";
+        name.value = link.name;
+        var url = document.createElement("input");
+        url.className = "social-link-url";
+        url.placeholder = "https://...";
+        url.value = link.url;
+        var remove = document.createElement("button");
+        remove.type = "button";
+        remove.innerText = "Remove";
+        remove.addEventListener("click", () => {
+            row.remove();
+        })
+        var error = document.createElement("div");
+        error.className = "settings-error";
+        row.append(name, url, remove);
+        this.social_links.append(row, error);
+    }
+
+    read_settings () {
+        var elements = this.form.elements;
+        return {
+            name: elements["name"].value,
+            description: elements["description"].value,
+            base_url: elements["base_url"].value,
+            default_author: elements["default_author"].value,
+            posts_per_page: Number(elements["posts_per_page"].value),
+            timezone: elements["timezone"].value,
+            date_format: elements["date_format"].value,
+            registration: elements["registration"].value,
+            comments: elements["comments"].value,
+            social_links: Array.from(this.social_links.querySelectorAll(".social-link")).map((row) => ({
+                name: row.querySelector(".social-link-name").value,
+                url: row.querySelector(".social-link-url").value
+            }))
+        };
+    }
+
+    // Errors are shown under their field, the ones of a social link under its row
+    show_errors (errors) {
+        this.container.querySelectorAll(".settings-error").forEach((element) => {
+            element.innerText = "";
+        })
+        var rows = this.social_links.querySelectorAll(".social-link");
+        for (const error of errors) {
+            var link = error.field.match(/^social_links\[(\d+)\]/);
+            var element = link
+                ? rows[Number(link[1])].nextElementSibling
+                : this.container.querySelector(`.settings-error[data-field="${error.field}"]`);
+            if (element) {
+                element.innerText = element.innerText ? `${element.innerText}, ${error.message}` : error.message;
+            }
+        }
+    }
+
+    save_settings () {
+        fetch(`${location.protocol}//${location.host}/dashboard/api/settings`, {
+            method: 'PUT',
+            cache: 'no-cache',
+            credentials: 'same-origin',
+            headers: {
+                'Content-Type': 'application/json'
+            },
+            body: JSON.stringify(this.read_settings())
+        }).then((response) => {
+            if (response.status == 200) {
+                response.json().then((settings) => {
+                    this.show_errors([]);
+                    this.show_settings(settings);
+                    this.status.removeAttribute("status");
+                    this.status.innerText = "Settings saved";
+                })
+            } else if (response.status == 422) {
+                response.json().then((problem) => {
+                    this.show_errors(problem.errors);
+                    this.status.setAttribute("status", "error");
+                    this.status.innerText = "Some settings are not valid";
+                })
+            } else {
+                problem_detail(response).then((detail) => {
+                    this.status.setAttribute("status", "error");
+                    this.status.innerText = detail;
+                })
+            }
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+}
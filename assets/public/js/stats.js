@@ -0,0 +1,105 @@
+window.addEventListener("load", () => {
+    var page_stats = new PageStats("#page-stats");
+})
+
+class PageStats {
+    constructor (container_selector) {
+        this.container = document.querySelector(container_selector);
+        this.days_select = this.container.querySelector(".stats-days-select");
+        this.totals = this.container.querySelector(".stats-totals");
+        this.trend = this.container.querySelector(".stats-trend");
+        this.top_articles = this.container.querySelector(".stats-top-articles tbody");
+        this.referrers = this.container.querySelector(".stats-referrers tbody");
+        // Trend of the whole site until an article is selected
+        this.article_id = null;
+
+        this.days_select.addEventListener("change", () => {
+            this.load();
+        })
+
+        this.load();
+    }
+
+    load () {
+        this.load_top();
+        this.load_trend();
+    }
+
+    fetch_json (path) {
+        return fetch(`${location.protocol}//${location.host}${path}`, {
+            method: 'GET',
+            cache: 'no-cache',
+            credentials: 'same-origin'
+        }).then((response) => response.json())
+    }
+
+    load_top () {
+        this.fetch_json(`/dashboard/api/stats/top?days=${this.days_select.value}&limit=10`).then((response) => {
+            this.top_articles.innerHTML = "";
+            for (const article of response.articles) {
+                var row = document.createElement("tr");
+                var title_cell = document.createElement("td");
+                var title = document.createElement("a");
+                title.href = "#";
+                title.innerText = article.title || article.id;
+                title.addEventListener("click", (event) => {
+                    event.preventDefault();
+                    this.select_article(article.id == this.article_id ? null : article.id);
+                })
+                title_cell.appendChild(title);
+                row.appendChild(title_cell);
+                row.appendChild(this.cell(article.views));
+                row.appendChild(this.cell(article.visitors));
+                row.toggleAttribute("selected", article.id == this.article_id);
+                row.dataset.articleId = article.id;
+                this.top_articles.appendChild(row);
+            }
+
+            this.referrers.innerHTML = "";
+            for (const referrer of response.referrers) {
+                var row = document.createElement("tr");
+                row.appendChild(this.cell(referrer.domain));
+                row.appendChild(this.cell(referrer.views));
+                this.referrers.appendChild(row);
+            }
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+
+    select_article (article_id) {
+        this.article_id = article_id;
+        for (const row of this.top_articles.querySelectorAll("tr")) {
+            row.toggleAttribute("selected", row.dataset.articleId == article_id);
+        }
+        this.load_trend();
+    }
+
+    load_trend () {
+        var article = this.article_id ? `&article=${encodeURIComponent(this.article_id)}` : "";
+        this.fetch_json(`/dashboard/api/stats/trend?days=${this.days_select.value}${article}`).then((response) => {
+            var views = response.days.reduce((total, day) => total + day.views, 0);
+            var visitors = response.days.reduce((total, day) => total + day.visitors, 0);
+            this.totals.innerText = `${views} views, ${visitors} visitors${this.article_id ? " of the selected article" : ""}`;
+
+            // Bars scaled to the busiest day
+            var busiest = Math.max(1, ...response.days.map((day) => day.views));
+            this.trend.innerHTML = "";
+            for (const day of response.days) {
+                var bar = document.createElement("div");
+                bar.className = "stats-trend-bar";
+                bar.style.height = `${(day.views / busiest) * 100}%`;
+                bar.title = `${day.date}: ${day.views} views, ${day.visitors} visitors`;
+                this.trend.appendChild(bar);
+            }
+        }).catch((err) => {
+            console.log(err);
+        })
+    }
+
+    cell (text) {
+        var cell = document.createElement("td");
+        cell.innerText = text;
+        return cell;
+    }
+}
@@ -0,0 +1,115 @@
+// Sends concurrent GET requests to a running server and reports the throughput
+//
+//   cargo run --release --example load_test -- [options] <path>...
+//
+//   --host <host:port>     server address, localhost:1337 by default
+//   --threads <n>          concurrent clients, 32 by default
+//   --seconds <n>          test duration, 10 by default
+//   --cookie <SESSID>      session id sent with every request, needed for dashboard paths
+//
+// Every client picks the paths in turn, one connection per request
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Options {
+    host: String,
+    threads: usize,
+    seconds: u64,
+    cookie: Option<String>,
+    paths: Vec<String>
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        host: String::from("localhost:1337"),
+        threads: 32,
+        seconds: 10,
+        cookie: None,
+        paths: Vec::new()
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("Missing value of {}", name));
+        match arg.as_str() {
+            "--host" => options.host = value("--host")?,
+            "--threads" => options.threads = value("--threads")?.parse().map_err(|_| "Invalid --threads")?,
+            "--seconds" => options.seconds = value("--seconds")?.parse().map_err(|_| "Invalid --seconds")?,
+            "--cookie" => options.cookie = Some(value("--cookie")?),
+            _ => options.paths.push(arg),
+        }
+    }
+
+    if options.paths.is_empty() {
+        return Err(String::from("Usage: load_test [--host host:port] [--threads n] [--seconds n] [--cookie SESSID] <path>..."));
+    }
+    Ok(options)
+}
+
+// Returns the status code of the response
+fn request(host: &str, path: &str, cookie: &Option<String>) -> std::io::Result<u16> {
+    let mut stream = TcpStream::connect(host)?;
+    let cookie_header = match cookie {
+        Some(sessid) => format!("Cookie: SESSID={}\r\n", sessid),
+        None => String::new(),
+    };
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n{}Connection: close\r\n\r\n", path, host, cookie_header)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let status = String::from_utf8_lossy(&response[..response.len().min(12)])
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .unwrap_or(0);
+    Ok(status)
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => Arc::new(options),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        },
+    };
+
+    let running = Arc::new(AtomicBool::new(true));
+    let completed = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicUsize::new(0));
+
+    let clients: Vec<thread::JoinHandle<()>> = (0..options.threads).map(|client| {
+        let options = options.clone();
+        let running = running.clone();
+        let completed = completed.clone();
+        let failed = failed.clone();
+        thread::spawn(move || {
+            let mut next_path = client;
+            while running.load(Ordering::Relaxed) {
+                let path = &options.paths[next_path % options.paths.len()];
+                next_path += 1;
+                match request(&options.host, path, &options.cookie) {
+                    Ok(status) if status < 400 => completed.fetch_add(1, Ordering::Relaxed),
+                    _ => failed.fetch_add(1, Ordering::Relaxed),
+                };
+            }
+        })
+    }).collect();
+
+    let start = Instant::now();
+    thread::sleep(Duration::from_secs(options.seconds));
+    running.store(false, Ordering::Relaxed);
+    for client in clients {
+        let _ = client.join();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let completed = completed.load(Ordering::Relaxed);
+    println!("{} requests in {:.1}s, {} failed", completed, elapsed, failed.load(Ordering::Relaxed));
+    println!("{:.0} requests/s with {} clients", completed as f64 / elapsed, options.threads);
+}
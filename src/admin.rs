@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, Error, ErrorKind, Result, stdin, stdout};
+use std::sync::Arc;
+use serde::Serialize;
+
+extern crate termion;
+use termion::{color};
+
+use crate::config::Config;
+use crate::helpers;
+use crate::rpublish::articles_manager::{ArticleStatus, ArticlesManager};
+use crate::rpublish::audit::{AuditAction, AuditLog};
+use crate::rpublish::articles_manager::listing::{ArticleFilter, ListingSort, ListingStatus, SortField, SortOrder};
+use crate::rpublish::identity_manager::{IdentityError, IdentityErrorKind, Sessions, UserPermissions, Users};
+use crate::rpublish::metadata_cache::ArticleMetadata;
+use crate::rpublish::storage::{Storage, StorageBackend};
+use crate::themes;
+
+pub const USAGE: &str = "Usage: rpublish [command]
+  Starts the server without a command
+
+  migrate [--from <backend>] --to <backend>      Copy the data to another storage backend
+  export --out <dir> [--base-url <url>] [--full] Render the public site into static files
+
+  user list [--json]
+  user add <username> [--admin] [--password-stdin]
+  user passwd <username> [--password-stdin]
+  user disable <username>                        Also logs out the user
+  user enable <username>
+  user grant <username> <admin|editor> [--revoke]
+
+  article list [--status draft|draft_only|changed|published] [--trash] [--json]
+  article publish|unpublish|delete|restore <id>
+
+  session list [--json]
+  session revoke <sessid> | --user <username> | --all
+
+  cache rebuild                                  Rebuild the metadata of every article
+  check                                          Check the data directory, fails on problems
+
+  User and session changes fail while the server runs as it would overwrite them, article
+  changes too unless watch_articles is on with the filesystem storage
+  Changes are recorded in the audit log as done by --editor <name>, rpublish by default
+  Passwords are prompted unless --password-stdin reads them from the first line of stdin";
+
+// Flags followed by a value, every other flag is a switch
+const VALUE_FLAGS: [&str; 3] = ["--status", "--editor", "--user"];
+
+// Author of the history commits and audit entries made from the command line when --editor is not given
+const DEFAULT_EDITOR: &str = "rpublish";
+
+struct CommandArgs {
+    positional: Vec<String>,
+    switches: HashSet<String>,
+    values: HashMap<String, String>
+}
+
+impl CommandArgs {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut parsed = Self {
+            positional: Vec::new(),
+            switches: HashSet::new(),
+            values: HashMap::new()
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if VALUE_FLAGS.contains(&arg.as_str()) {
+                match args.next() {
+                    Some(value) => parsed.values.insert(arg.to_owned(), value.to_owned()),
+                    None => return Err(usage_error(&format!("Missing value of {}", arg))),
+                };
+            } else if arg.starts_with("--") {
+                parsed.switches.insert(arg.to_owned());
+            } else {
+                parsed.positional.push(arg.to_owned());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+
+    // Positional argument after the command and action
+    fn argument(&self, index: usize, name: &str) -> Result<&str> {
+        self.positional.get(index)
+            .map(|value| value.as_str())
+            .ok_or_else(|| usage_error(&format!("Missing <{}>", name)))
+    }
+}
+
+fn usage_error(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("{}\n\n{}", message, USAGE))
+}
+
+// Failed saves keep their io error, the other identity errors come from the input
+fn identity_error(error: IdentityError) -> Error {
+    let message = error.to_string();
+    match error.into_kind() {
+        IdentityErrorKind::Storage(error) => error,
+        _ => Error::new(ErrorKind::InvalidInput, message),
+    }
+}
+
+// Colors are left out when the output goes to a pipe or a file
+fn print_colored(message: &str, foreground: &dyn color::Color) {
+    if termion::is_tty(&stdout()) {
+        println!("{}{}{}", color::Fg(foreground), message, color::Fg(color::Reset));
+    } else {
+        println!("{}", message);
+    }
+}
+
+fn done(message: &str) {
+    print_colored(message, &color::Cyan);
+}
+
+// Kept until the command ends so the server can't start in the middle of it, reason tells
+// why the change can't be done while the server runs
+fn lock_data_dir(reason: &str) -> Result<File> {
+    match helpers::lock_data_dir()? {
+        Some(lock) => Ok(lock),
+        None => {
+            let pid = helpers::read_lock_pid().map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+            Err(Error::new(
+                ErrorKind::WouldBlock,
+                format!("The server{} is running on this data directory and {}, stop it first", pid, reason)
+            ))
+        },
+    }
+}
+
+// Work on the data directory without starting the server. A running server keeps users and
+// sessions in memory and saves them over any change, and only sees article changes through
+// the articles watcher of the filesystem storage. Those commands refuse to run while it
+// holds the data directory
+pub fn run(config: &Config, args: &[String]) -> Result<()> {
+    let command = CommandArgs::parse(args)?;
+    let watched = config.watch_articles && config.storage == StorageBackend::Filesystem;
+    let _data_lock = match (command.argument(0, "command")?, command.positional.get(1).map(|action| action.as_str())) {
+        ("user" | "session", Some(action)) if action != "list" => Some(lock_data_dir("would overwrite the change")?),
+        // The watcher reloads the changed files, the lock is only taken when the server is stopped
+        ("article", Some(action)) if action != "list" && watched => helpers::lock_data_dir()?,
+        ("article", Some(action)) if action != "list" => Some(lock_data_dir("won't see the change without watch_articles")?),
+        _ => None,
+    };
+    let storage = config.storage.open(&config.sqlite_path)?;
+    // Command line changes have no ip in the audit log
+    let audit = Audit {
+        log: AuditLog::open()?,
+        actor: command.value("--editor").unwrap_or(DEFAULT_EDITOR).to_string()
+    };
+
+    match (command.argument(0, "command")?, command.positional.get(1).map(|action| action.as_str())) {
+        ("user", Some(action)) => user(storage, &audit, action, &command),
+        ("article", Some(action)) => article(config, storage, &audit, action, &command),
+        ("session", Some(action)) => session(storage, &audit, action, &command),
+        ("cache", Some("rebuild")) => {
+            ArticlesManager::new(storage, 0, None).rebuild_metadata();
+            done("Metadata rebuilt");
+            Ok(())
+        },
+        ("check", None) => check(config, storage.as_ref()),
+        _ => Err(usage_error("Unknown command")),
+    }
+}
+
+struct Audit {
+    log: AuditLog,
+    actor: String
+}
+
+impl Audit {
+    fn record(&self, action: AuditAction, target: Option<&str>, detail: Option<&str>) {
+        self.log.record(action, &self.actor, None, target, detail);
+    }
+}
+
+#[derive(Serialize)]
+struct UserRow<'a> {
+    username: &'a str,
+    enabled: bool,
+    permissions: Vec<&'static str>,
+    created_date: String
+}
+
+fn user(storage: Arc<dyn Storage>, audit: &Audit, action: &str, command: &CommandArgs) -> Result<()> {
+    let mut users = Users::open(storage.clone())?;
+    match action {
+        "list" => {
+            let rows: Vec<UserRow> = users.list().iter()
+                .map(|user| UserRow {
+                    username: user.user_name(),
+                    enabled: user.is_enabled(),
+                    permissions: user.permissions().iter().map(|permission| permission.name()).collect(),
+                    created_date: user.created_date().to_rfc3339()
+                })
+                .collect();
+            if command.switch("--json") {
+                println!("{}", serde_json::to_string_pretty(&rows).map_err(Error::other)?);
+            } else {
+                for row in rows {
+                    println!(
+                        "{:<20} {:<9} {:<14} {}",
+                        row.username,
+                        if row.enabled { "enabled" } else { "disabled" },
+                        row.permissions.join(","),
+                        row.created_date
+                    );
+                }
+            }
+            Ok(())
+        },
+        "add" => {
+            let username = command.argument(2, "username")?;
+            let password = read_password(command)?;
+            let permissions = if command.switch("--admin") {
+                vec![UserPermissions::Admin, UserPermissions::Editor]
+            } else {
+                vec![UserPermissions::Editor]
+            };
+            users.create(username, &password, &permissions).map_err(identity_error)?;
+            let permission_names: Vec<&str> = permissions.iter().map(|permission| permission.name()).collect();
+            audit.record(AuditAction::UserCreate, Some(username), Some(&permission_names.join(",")));
+            done(&format!("User {} created", username));
+            Ok(())
+        },
+        "passwd" => {
+            let username = command.argument(2, "username")?;
+            users.get(username).map_err(identity_error)?;
+            let password = read_password(command)?;
+            users.set_password(username, &password).map_err(identity_error)?;
+            audit.record(AuditAction::UserPassword, Some(username), None);
+            done(&format!("Password of {} changed", username));
+            Ok(())
+        },
+        "disable" | "enable" => {
+            let username = command.argument(2, "username")?;
+            users.set_enabled(username, action == "enable").map_err(identity_error)?;
+            if action == "disable" {
+                let removed = Sessions::load_sessions(storage)?.invalidate_user(username)?;
+                audit.record(AuditAction::UserDisable, Some(username), Some(&format!("{} sessions revoked", removed)));
+                done(&format!("User {} disabled, {} sessions revoked", username, removed));
+            } else {
+                audit.record(AuditAction::UserEnable, Some(username), None);
+                done(&format!("User {} enabled", username));
+            }
+            Ok(())
+        },
+        "grant" => {
+            let username = command.argument(2, "username")?;
+            let permission = UserPermissions::parse(command.argument(3, "admin|editor")?)
+                .ok_or_else(|| usage_error("Permissions are admin or editor"))?;
+            if command.switch("--revoke") {
+                users.revoke(username, permission).map_err(identity_error)?;
+                audit.record(AuditAction::PermissionRevoke, Some(username), Some(permission.name()));
+                done(&format!("Revoked {} from {}", permission.name(), username));
+            } else {
+                users.grant(username, permission).map_err(identity_error)?;
+                audit.record(AuditAction::PermissionGrant, Some(username), Some(permission.name()));
+                done(&format!("Granted {} to {}", permission.name(), username));
+            }
+            Ok(())
+        },
+        _ => Err(usage_error("Unknown user command")),
+    }
+}
+
+// From the first line of stdin with --password-stdin, prompted twice otherwise
+fn read_password(command: &CommandArgs) -> Result<String> {
+    let password = if command.switch("--password-stdin") {
+        let mut password = String::new();
+        stdin().lock().read_line(&mut password)?;
+        password.trim_end_matches(['\r', '\n']).to_string()
+    } else if termion::is_tty(&stdin()) {
+        let password = Users::read_input_hidden("Password: ").unwrap_or_default();
+        let password_repeat = Users::read_input_hidden("Password (repeat): ").unwrap_or_default();
+        println!();
+        if password != password_repeat {
+            return Err(Error::new(ErrorKind::InvalidInput, "Passwords don't match"));
+        }
+        password
+    } else {
+        return Err(usage_error("No terminal to prompt the password, use --password-stdin"));
+    };
+    if password.trim().is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Empty password"));
+    }
+    Ok(password)
+}
+
+#[derive(Serialize)]
+struct ArticleRow<'a> {
+    id: &'a str,
+    title: &'a str,
+    author: &'a str,
+    update_date: String
+}
+
+fn article(config: &Config, storage: Arc<dyn Storage>, audit: &Audit, action: &str, command: &CommandArgs) -> Result<()> {
+    let editor = audit.actor.as_str();
+    if action == "list" {
+        return list_articles(storage, command);
+    }
+
+    let article_id = command.argument(2, "id")?;
+    let history = match action {
+        "publish" | "unpublish" | "delete" | "restore" => crate::open_history(config),
+        _ => return Err(usage_error("Unknown article command")),
+    };
+    let articles_manager = ArticlesManager::new(storage, 0, history);
+    let exists = {
+        let index = articles_manager.index();
+        index.is_listed(article_id, ArticleStatus::Draft) || index.is_listed(article_id, ArticleStatus::Published)
+    };
+    if !exists && action != "restore" {
+        return Err(Error::new(ErrorKind::NotFound, format!("Article {} not found", article_id)));
+    }
+
+    let audit_action = match action {
+        "publish" => {
+            articles_manager.publish(article_id, editor)?;
+            AuditAction::ArticlePublish
+        },
+        "unpublish" => {
+            articles_manager.unpublish(article_id, editor)?;
+            AuditAction::ArticleUnpublish
+        },
+        "delete" => {
+            articles_manager.delete(article_id, editor)?;
+            AuditAction::ArticleDelete
+        },
+        _ => {
+            articles_manager.restore(article_id, editor)?;
+            AuditAction::ArticleRestore
+        },
+    };
+    audit.record(audit_action, Some(article_id), None);
+    done(&format!("Article {} {}", article_id, match action {
+        "publish" => "published",
+        "unpublish" => "unpublished",
+        "delete" => "moved to the trash",
+        _ => "restored",
+    }));
+    Ok(())
+}
+
+fn list_articles(storage: Arc<dyn Storage>, command: &CommandArgs) -> Result<()> {
+    let articles_manager = ArticlesManager::new(storage, 0, None);
+    if command.switch("--trash") {
+        for article_id in articles_manager.list_trashed() {
+            println!("{}", article_id);
+        }
+        return Ok(());
+    }
+
+    let status = match command.value("--status") {
+        Some(status) => Some(
+            serde_json::from_value::<ListingStatus>(serde_json::Value::from(status))
+                .map_err(|_| usage_error("Unknown --status"))?
+        ),
+        None => None,
+    };
+    let filter = ArticleFilter { status, ..ArticleFilter::default() };
+    let sort = ListingSort { field: SortField::UpdateDate, order: SortOrder::Desc };
+    let index = articles_manager.index();
+    let listing = index.query_articles(&filter, 0, usize::MAX, sort, None);
+    let rows: Vec<ArticleRow> = listing.articles.iter()
+        .map(|item| ArticleRow {
+            id: item.id,
+            title: &item.metadata.title,
+            author: &item.metadata.author,
+            update_date: item.metadata.update_date.to_rfc3339()
+        })
+        .collect();
+
+    if command.switch("--json") {
+        println!("{}", serde_json::to_string_pretty(&rows).map_err(Error::other)?);
+    } else {
+        for row in rows {
+            println!("{:<34} {:<26} {:<16} {}", row.id, row.update_date, row.author, row.title);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct SessionRow<'a> {
+    sessid: &'a str,
+    username: &'a str,
+    ip: &'a str,
+    date: String
+}
+
+fn session(storage: Arc<dyn Storage>, audit: &Audit, action: &str, command: &CommandArgs) -> Result<()> {
+    let sessions = Sessions::load_sessions(storage)?;
+    match action {
+        "list" => {
+            let listed = sessions.list();
+            let rows: Vec<SessionRow> = listed.iter()
+                .map(|(sessid, session)| SessionRow {
+                    sessid,
+                    username: session.username(),
+                    ip: session.ip(),
+                    date: session.date().to_rfc3339()
+                })
+                .collect();
+            if command.switch("--json") {
+                println!("{}", serde_json::to_string_pretty(&rows).map_err(Error::other)?);
+            } else {
+                for row in rows {
+                    println!("{:<64} {:<20} {:<22} {}", row.sessid, row.username, row.ip, row.date);
+                }
+            }
+            Ok(())
+        },
+        "revoke" => {
+            // The session ids are secrets, the audit log only names the user
+            let removed = if command.switch("--all") {
+                let removed = sessions.invalidate_all()?;
+                audit.record(AuditAction::SessionRevoke, None, Some(&format!("all {} sessions", removed)));
+                removed
+            } else if let Some(username) = command.value("--user") {
+                let removed = sessions.invalidate_user(username)?;
+                audit.record(AuditAction::SessionRevoke, Some(username), Some(&format!("{} sessions", removed)));
+                removed
+            } else {
+                let sessid = command.argument(2, "sessid")?;
+                let username = match sessions.get_user(sessid) {
+                    Some(username) => username,
+                    None => return Err(Error::new(ErrorKind::NotFound, "Session not found")),
+                };
+                sessions.invalidate(sessid)?;
+                audit.record(AuditAction::SessionRevoke, Some(&username), Some("single session"));
+                1
+            };
+            done(&format!("{} sessions revoked", removed));
+            Ok(())
+        },
+        _ => Err(usage_error("Unknown session command")),
+    }
+}
+
+// Read only checks of the data, every problem found is listed
+fn check(config: &Config, storage: &dyn Storage) -> Result<()> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if themes::read_manifest(&config.theme).is_none() {
+        problems.push(format!("Theme {} not found or missing theme.json", config.theme));
+    }
+
+    match storage.load_users()? {
+        Some(users) => {
+            let enabled_admin = users.iter().any(|user| {
+                user.is_enabled() && user.permissions().contains(&UserPermissions::Admin)
+            });
+            if !enabled_admin {
+                problems.push(String::from("No enabled admin user"));
+            }
+            if let Some(sessions) = storage.load_sessions()? {
+                for (sessid, session) in &sessions {
+                    match users.iter().find(|user| user.user_name() == session.username()) {
+                        Some(user) if user.is_enabled() => {},
+                        Some(_) => problems.push(format!("Session {} belongs to disabled user {}", sessid, session.username())),
+                        None => problems.push(format!("Session {} belongs to unknown user {}", sessid, session.username())),
+                    }
+                }
+            }
+        },
+        None => problems.push(String::from("No users, the first admin is created on the next start")),
+    }
+
+    let mut articles = 0;
+    for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+        let metadata: HashMap<String, ArticleMetadata> = storage.load_metadata(status.dir_name());
+        let article_ids = storage.list_articles(status);
+        for article_id in &article_ids {
+            match storage.read_article(article_id, status) {
+                Some(article) => match metadata.get(article_id) {
+                    Some(entry) if entry.update_date == article.update_date => {},
+                    Some(_) => problems.push(format!("Outdated metadata of {} article {}", status.dir_name(), article_id)),
+                    None => problems.push(format!("Missing metadata of {} article {}", status.dir_name(), article_id)),
+                },
+                None => problems.push(format!("Unreadable {} article {}", status.dir_name(), article_id)),
+            }
+        }
+        for article_id in metadata.keys().filter(|article_id| !article_ids.contains(article_id)) {
+            problems.push(format!("Metadata of missing {} article {}", status.dir_name(), article_id));
+        }
+        articles += article_ids.len();
+    }
+
+    if problems.is_empty() {
+        done(&format!("{} articles checked, no problems found", articles));
+        Ok(())
+    } else {
+        for problem in &problems {
+            print_colored(problem, &color::Red);
+        }
+        Err(Error::other(format!("{} problems found, metadata problems are fixed by `rpublish cache rebuild` or a restart", problems.len())))
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::io::ErrorKind;
+use serde::{Serialize, Deserialize};
+
+use crate::helpers::write_json;
+use crate::logging::LogFormat;
+use crate::rpublish::storage::StorageBackend;
+
+const CONFIG_PATH: &str = "data/config.json";
+
+// Address the http server listens on
+pub const BIND_ADDRESS: &str = "0.0.0.0:1337";
+// Url of the server on the local machine, used for absolute links when the site has no base url
+pub const LOCAL_BASE_URL: &str = "http://localhost:1337";
+
+// Server configuration stored in data/config.json, missing fields take their default value
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Config {
+    // Theme used by the public site
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    // Where articles, metadata, users and sessions are stored
+    #[serde(default)]
+    pub storage: StorageBackend,
+    // Database file used by the sqlite storage backend
+    #[serde(default = "default_sqlite_path")]
+    pub sqlite_path: String,
+    // Memory used to keep read articles and rendered pages, in megabytes, 0 disables it
+    #[serde(default = "default_articles_cache_mb")]
+    pub articles_cache_mb: usize,
+    // Reload article files changed outside the server, filesystem storage only
+    #[serde(default)]
+    pub watch_articles: bool,
+    // Commit every article change into a git repository at data/, filesystem storage only
+    #[serde(default)]
+    pub git_history: bool,
+    // Path or url of the repository the history is pushed to
+    #[serde(default)]
+    pub git_remote: Option<String>,
+    // Create the first admin from the browser when there are no users and no other way,
+    // also enabled with RPUBLISH_SETUP_WIZARD=1
+    #[serde(default)]
+    pub setup_wizard: bool,
+    // Lowest level written to the log, error, warn, info, debug or trace,
+    // RPUBLISH_LOG_LEVEL overrides it
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    // human or json lines, RPUBLISH_LOG_FORMAT overrides it
+    #[serde(default)]
+    pub log_format: LogFormat,
+    // Size of data/logs files before they are rotated, in megabytes, 0 never rotates
+    #[serde(default = "default_log_max_size_mb")]
+    pub log_max_size_mb: u64,
+    // Rotated files kept for every log
+    #[serde(default = "default_log_files")]
+    pub log_files: usize,
+    // Write every request to data/logs/access.log
+    #[serde(default = "default_access_log")]
+    pub access_log: bool,
+    // Bearer token required by /metrics, RPUBLISH_METRICS_TOKEN overrides it
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+    // Only answer /metrics to requests from the same machine
+    #[serde(default)]
+    pub metrics_localhost_only: bool,
+    // Take the client address from the Forwarded or X-Forwarded-For header, only for a server
+    // behind a reverse proxy that sets it, any client can send these headers
+    #[serde(default)]
+    pub trusted_proxy: bool
+}
+
+fn default_theme() -> String {
+    String::from("default")
+}
+
+fn default_sqlite_path() -> String {
+    String::from("data/rpublish.sqlite3")
+}
+
+fn default_articles_cache_mb() -> usize {
+    32
+}
+
+fn default_log_level() -> String {
+    String::from("info")
+}
+
+fn default_log_max_size_mb() -> u64 {
+    10
+}
+
+fn default_log_files() -> usize {
+    5
+}
+
+fn default_access_log() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: default_theme(),
+            storage: StorageBackend::default(),
+            sqlite_path: default_sqlite_path(),
+            articles_cache_mb: default_articles_cache_mb(),
+            watch_articles: false,
+            git_history: false,
+            git_remote: None,
+            setup_wizard: false,
+            log_level: default_log_level(),
+            log_format: LogFormat::default(),
+            log_max_size_mb: default_log_max_size_mb(),
+            log_files: default_log_files(),
+            access_log: default_access_log(),
+            metrics_token: None,
+            metrics_localhost_only: false,
+            trusted_proxy: false
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        match fs::read_to_string(CONFIG_PATH) {
+            Ok(config_string) => {
+                match serde_json::from_str::<Self>(config_string.as_str()) {
+                    Ok(config) => {
+                        log::info!("Config loaded");
+                        config
+                    },
+                    Err(error) => {
+                        log::error!("Failed to parse config file, using defaults: {}", error);
+                        Self::default()
+                    },
+                }
+            },
+            Err(error) => {
+                let config = Self::default();
+                if error.kind() == ErrorKind::NotFound {
+                    log::info!("Config file not found, creating one");
+                    config.save();
+                } else {
+                    log::error!("Error reading config file, using defaults: {}", error);
+                }
+                config
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                match write_json(CONFIG_PATH, json) {
+                    Ok(_) => log::info!("Config file saved"),
+                    Err(error) => log::error!("Failed to save config file: {}", error),
+                }
+            },
+            Err(error) => log::error!("Failed to serialize config file: {}", error)
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, http::StatusCode};
+use actix_web::error::{BlockingError, JsonPayloadError};
+use serde::Serialize;
+use tera::Context;
+
+use crate::rpublish::articles_manager::{ArticleError, ArticleErrorKind};
+use crate::rpublish::editorjs::escape_html;
+use crate::rpublish::identity_manager::{IdentityError, IdentityErrorKind};
+use crate::templates::Templates;
+
+// Problem with one field of a submitted form, field is the json path like "social_links[1].url"
+#[derive(Serialize, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self { field: field.to_string(), message: message.to_string() }
+    }
+}
+
+// Failures of the request handlers. They are answered with a problem details json body
+// (RFC 7807), the ErrorPages middleware replaces it with an error page on the html routes
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    // The request doesn't fit the current state, like publishing an article without changes
+    Conflict(String),
+    BadRequest(String),
+    // The request body is over the size limit
+    TooLarge(String),
+    // Every invalid field of a submitted form
+    Validation(Vec<FieldError>),
+    // The session cookie is missing or the session ended after the login check
+    Unauthorized(String),
+    Forbidden(String),
+    // Reading or writing the data failed, the error is logged and not sent
+    Io(std::io::Error),
+    // Panics and failed renders, logged where they happen
+    Internal,
+}
+
+// Json body of the errors answered to the api routes
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<&'a [FieldError]>
+}
+
+// Api clients get json errors, the other routes are pages opened in a browser
+pub fn is_api_path(path: &str) -> bool {
+    ["/api", "/dashboard/api"].iter()
+        .any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+}
+
+// Json bodies over the limit and malformed ones
+pub fn json_error(error: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match error {
+        JsonPayloadError::Overflow => AppError::TooLarge(error.to_string()),
+        _ => AppError::BadRequest(error.to_string()),
+    }.into()
+}
+
+impl AppError {
+    pub fn session_ended() -> Self {
+        AppError::Unauthorized(String::from("Your session ended, log in again"))
+    }
+
+    // Page shown for the error on the html routes
+    pub fn page(&self, templates: Option<&Templates>, path: &str) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type("text/html; charset=utf-8")
+            .body(self.page_html(templates, path))
+    }
+
+    // Html of the error page, a plain page when the template fails
+    pub fn page_html(&self, templates: Option<&Templates>, path: &str) -> String {
+        let status = self.status_code();
+        let title = status.canonical_reason().unwrap_or("Error");
+        let link = match self {
+            AppError::Unauthorized(_) => "/auth/login",
+            _ if path.starts_with("/dashboard") || path.starts_with("/auth") => "/dashboard",
+            _ => "/",
+        };
+
+        let mut context = Context::new();
+        context.insert("title", title);
+        context.insert("status", &status.as_u16());
+        context.insert("message", &self.to_string());
+        context.insert("link", link);
+        let page = templates.map(|templates| templates.render("error.html", &context));
+        match page {
+            Some(Ok(page)) => page,
+            failed => {
+                if let Some(Err(error)) = failed {
+                    log::error!("Failed to render error.html: {:?}", error);
+                }
+                format!(
+                    "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"UTF-8\"><title>{title}</title></head>\n<body><h1>{status} {title}</h1><p>{message}</p></body></html>\n",
+                    title = title,
+                    status = status.as_u16(),
+                    message = escape_html(&self.to_string())
+                )
+            },
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::BadRequest(message)
+            | AppError::TooLarge(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message) => write!(f, "{}", message),
+            AppError::Validation(_) => write!(f, "Some fields are not valid"),
+            AppError::Io(_) => write!(f, "The server failed to read or write its data"),
+            AppError::Internal => write!(f, "Something went wrong, try again later"),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Io(_) | AppError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // Server errors are logged with their cause by actix
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            errors: match self {
+                AppError::Validation(errors) => Some(errors),
+                _ => None,
+            }
+        };
+        HttpResponse::build(status)
+            .content_type("application/problem+json")
+            .body(serde_json::to_string(&problem).unwrap_or_default())
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArticleError> for AppError {
+    fn from(error: ArticleError) -> Self {
+        let message = error.to_string();
+        match error.into_kind() {
+            ArticleErrorKind::ArticleNotFound => AppError::NotFound(message),
+            ArticleErrorKind::ArticleNotPublished
+            | ArticleErrorKind::NoDraft => AppError::Conflict(message),
+            ArticleErrorKind::Storage(error) => AppError::Io(error),
+        }
+    }
+}
+
+impl From<IdentityError> for AppError {
+    fn from(error: IdentityError) -> Self {
+        let message = error.to_string();
+        match error.into_kind() {
+            IdentityErrorKind::UserNotFound => AppError::NotFound(message),
+            IdentityErrorKind::UserAlreadyExist => AppError::Conflict(message),
+            IdentityErrorKind::AuthFailed => AppError::Unauthorized(message),
+            IdentityErrorKind::UserDisabled | IdentityErrorKind::InvalidSetupToken => AppError::Forbidden(message),
+            IdentityErrorKind::Storage(error) => AppError::Io(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::Io(error)
+    }
+}
+
+impl From<Vec<FieldError>> for AppError {
+    fn from(errors: Vec<FieldError>) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+// Errors of the closures run with web::block
+impl<E: Into<AppError> + fmt::Debug> From<BlockingError<E>> for AppError {
+    fn from(error: BlockingError<E>) -> Self {
+        match error {
+            BlockingError::Error(error) => error.into(),
+            BlockingError::Canceled => {
+                log::error!("Blocking call canceled");
+                AppError::Internal
+            },
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::config::Config;
+use crate::helpers;
+use crate::rpublish::articles_manager::ArticlesManager;
+use crate::rpublish::metadata_cache::ArticleMetadata;
+use crate::rpublish::storage::is_safe_id;
+use crate::settings::SiteSettings;
+use crate::site::{self, SiteLinks};
+use crate::templates::Templates;
+use crate::themes;
+
+const USAGE: &str = "Usage: rpublish export --out <dir> [--base-url <url>] [--full]";
+
+// Kept in the output directory to export only the articles changed since the last run
+const MANIFEST_FILE: &str = ".rpublish-export.json";
+
+struct ExportOptions {
+    out: PathBuf,
+    // Absolute links, also needed for the feed and sitemap. Links are relative without it
+    base_url: Option<String>,
+    // Render every article even if it didn't change
+    full: bool
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ExportManifest {
+    theme: String,
+    base_url: Option<String>,
+    // Site settings the pages were rendered with, as json
+    #[serde(default)]
+    settings: String,
+    // Update date of every exported article
+    articles: HashMap<String, DateTime<Utc>>
+}
+
+impl ExportOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut out = None;
+        let mut base_url = None;
+        let mut full = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => out = args.next().map(PathBuf::from),
+                "--base-url" => base_url = args.next().map(|url| url.trim_end_matches('/').to_string()),
+                "--full" => full = true,
+                _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+            }
+        }
+        match out {
+            Some(out) => Ok(Self { out, base_url, full }),
+            None => Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+}
+
+// `rpublish export --out <dir>` renders the published articles, listings, feed and sitemap
+// with the selected theme into a static site, along with the public files and uploads
+pub fn export(config: &Config, args: &[String]) -> Result<()> {
+    let options = ExportOptions::parse(args)?;
+    fs::create_dir_all(&options.out)?;
+
+    let storage = config.storage.open(&config.sqlite_path)?;
+    // Every article is rendered once, no need to cache them
+    let articles_manager = ArticlesManager::new(storage, 0, None);
+    let templates = Templates::new(&config.theme, false);
+    let theme = templates.theme();
+    let settings = SiteSettings::load();
+
+    let manifest_path = options.out.join(MANIFEST_FILE);
+    let previous: ExportManifest = fs::read_to_string(&manifest_path).ok()
+        .and_then(|manifest| serde_json::from_str(&manifest).ok())
+        .unwrap_or_default();
+    let settings_json = serde_json::to_string(&settings).map_err(Error::other)?;
+    // Every page changes with the theme, the links or the site settings
+    let full = options.full || previous.theme != theme || previous.base_url != options.base_url || previous.settings != settings_json;
+
+    let links = match &options.base_url {
+        Some(base_url) => SiteLinks::base_url(base_url),
+        None => SiteLinks::Relative(0),
+    };
+    let feed = options.base_url.is_some();
+    let seo_base_url = options.base_url.to_owned().unwrap_or_default();
+    let articles = articles_manager.index().published_by_date();
+
+    let mut manifest = ExportManifest {
+        theme: theme.to_owned(),
+        base_url: options.base_url.to_owned(),
+        settings: settings_json,
+        articles: HashMap::new()
+    };
+    let mut rendered = 0;
+    for (article_id, metadata) in &articles {
+        if !is_safe_id(article_id) {
+            continue;
+        }
+        let path = site::article_path(article_id);
+        let page_file = options.out.join(&path).join("index.html");
+        let unchanged = previous.articles.get(article_id) == Some(&metadata.update_date) && page_file.is_file();
+        if full || !unchanged {
+            let cached = match articles_manager.read_published(article_id) {
+                Some(cached) => cached,
+                None => {
+                    log::error!("Failed to read {}, skipping it", article_id);
+                    continue;
+                },
+            };
+            let context = site::article_context(&links.at(&path), &settings, article_id, &cached, &seo_base_url, feed);
+            write_page(&templates, "theme/article.html", &context, &page_file)?;
+            rendered += 1;
+        }
+        manifest.articles.insert(article_id.to_owned(), metadata.update_date);
+    }
+
+    // Pages of articles no longer published
+    let mut removed = 0;
+    for article_id in previous.articles.keys().filter(|article_id| !manifest.articles.contains_key(*article_id)) {
+        if is_safe_id(article_id) {
+            let _ = fs::remove_dir_all(options.out.join(site::article_path(article_id)));
+            removed += 1;
+        }
+    }
+    log::info!(
+        "{} articles rendered, {} unchanged, {} removed",
+        rendered, manifest.articles.len() - rendered, removed
+    );
+
+    // Listings are cheap to render, they are rebuilt every time so no stale page is left
+    for listing_dir in ["page", "tag"] {
+        let _ = fs::remove_dir_all(options.out.join(listing_dir));
+    }
+    let listing_pages = write_listing(&templates, &links, &settings, &options.out, &articles, None, feed)?;
+    let tags = site::tags(&articles);
+    for (slug, (name, tagged)) in &tags {
+        write_listing(&templates, &links, &settings, &options.out, tagged, Some((slug, name)), feed)?;
+    }
+    log::info!("{} index pages and {} tags written", listing_pages, tags.len());
+
+    if options.base_url.is_some() {
+        fs::write(options.out.join("feed.xml"), site::feed_xml(&links, &settings, &articles))?;
+        fs::write(options.out.join("sitemap.xml"), site::sitemap_xml(&links, &settings, &articles))?;
+    } else {
+        log::info!("No --base-url given, feed.xml and sitemap.xml are left out");
+    }
+
+    let mut copied = copy_files(Path::new("assets/public"), &options.out.join("public"))?;
+    copied += copy_files(Path::new("data/public"), &options.out.join("uploads"))?;
+    for (relative, file) in themes::static_files(&theme) {
+        copied += copy_file(&file, &options.out.join("theme").join(relative))? as usize;
+    }
+    log::info!("{} static files copied", copied);
+
+    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(Error::other)?;
+    helpers::write_json(&manifest_path.to_string_lossy(), manifest_json)?;
+    log::info!("Site exported to {}", options.out.display());
+    Ok(())
+}
+
+// Write every page of a listing, returns the number of pages
+fn write_listing(
+    templates: &Templates,
+    links: &SiteLinks,
+    settings: &SiteSettings,
+    out: &Path,
+    articles: &[(String, ArticleMetadata)],
+    tag: Option<(&str, &str)>,
+    feed: bool
+) -> Result<usize> {
+    let page_count = site::page_count(articles.len(), settings);
+    for page in 1..=page_count {
+        let path = site::listing_path(tag.map(|(slug, _)| slug), page);
+        if let Some(context) = site::listing_context(&links.at(&path), settings, articles, tag, page, feed) {
+            write_page(templates, "theme/index.html", &context, &out.join(&path).join("index.html"))?;
+        }
+    }
+    Ok(page_count)
+}
+
+fn write_page(templates: &Templates, template_name: &str, context: &tera::Context, path: &Path) -> Result<()> {
+    let page = templates.render(template_name, context)
+        .map_err(|error| Error::other(format!("Failed to render {}: {:?}", template_name, error)))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, page)
+}
+
+// Copy the files missing or changed in the target directory, returns the number copied
+fn copy_files(from: &Path, to: &Path) -> Result<usize> {
+    let mut copied = 0;
+    for file in themes::walk_files(from, None) {
+        if let Ok(relative) = file.strip_prefix(from) {
+            copied += copy_file(&file, &to.join(relative))? as usize;
+        }
+    }
+    Ok(copied)
+}
+
+// Files are copied when the size differs or the origin is newer than the copy
+fn copy_file(from: &Path, to: &Path) -> Result<bool> {
+    let origin = fs::metadata(from)?;
+    if let Ok(target) = fs::metadata(to) {
+        let newer = match (origin.modified(), target.modified()) {
+            (Ok(origin_time), Ok(target_time)) => origin_time > target_time,
+            _ => true,
+        };
+        if target.len() == origin.len() && !newer {
+            return Ok(false);
+        }
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+    Ok(true)
+}
@@ -1,4 +1,38 @@
 pub mod public;
 pub mod auth;
 pub mod api;
-pub mod dashboard;
\ No newline at end of file
+pub mod dashboard;
+pub mod health;
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::dev::ConnectionInfo;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::locks::MutexRecover;
+
+// Address of the client as recorded in sessions, the audit log, the access log and the page
+// statistics. Without the port, it changes with every connection. The forwarded address is
+// only trusted with the trusted_proxy option
+pub fn remote_ip(req: &HttpRequest) -> String {
+    client_ip(req.peer_addr(), &req.connection_info(), req.app_data::<web::Data<Mutex<Config>>>())
+}
+
+// remote_ip from the parts of the request, middlewares only have a service request
+pub fn client_ip(peer_addr: Option<SocketAddr>, connection_info: &ConnectionInfo, config: Option<&web::Data<Mutex<Config>>>) -> String {
+    if config.is_some_and(|config| config.lock_or_recover().trusted_proxy) {
+        if let Some(address) = connection_info.realip_remote_addr() {
+            return address.parse::<SocketAddr>()
+                .map(|address| address.ip().to_string())
+                .unwrap_or_else(|_| address.to_string());
+        }
+    }
+    peer_addr.map(|address| address.ip().to_string()).unwrap_or_default()
+}
+
+// Answer of the urls that match no route
+pub async fn not_found() -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound(String::from("Page not found")))
+}
@@ -1,15 +1,10 @@
 use actix_web::{Responder, web};
-use std::sync::Mutex;
-use crate::rpublish;
 
 pub fn configure (cfg: &mut web::ServiceConfig)
 {
 	cfg.route( "", web::get().to(api) );
 }
 
-pub async fn api(app: web::Data<Mutex<rpublish::RPublishApp>>) -> impl Responder {
-    // Aquire app reference
-    let mut _app = app.lock().unwrap();
-
+pub async fn api() -> impl Responder {
     "api".to_string()
 }
\ No newline at end of file
@@ -1,6 +1,13 @@
 use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, cookie::Cookie, http, web};
-use std::{fs, sync::Mutex};
+use actix_web::error::BlockingError;
+use tera::Context;
+use crate::error::AppError;
+use crate::handlers::remote_ip;
+use crate::locks::RwLockRecover;
 use crate::rpublish;
+use crate::rpublish::audit::AuditAction;
+use crate::rpublish::identity_manager::{IdentityError, IdentityErrorKind};
+use crate::templates::Templates;
 
 use serde::{Deserialize};
 
@@ -11,24 +18,29 @@ pub fn configure (cfg: &mut web::ServiceConfig)
 	cfg.route( "", web::get().to(home) )
 	   .route( "/logout", web::get().to(logout) )
        .route( "/login", web::get().to(login) )
-       .route( "/login", web::post().to(login_post) );
+       .route( "/login", web::post().to(login_post) )
+       .route( "/setup", web::get().to(setup) )
+       .route( "/setup", web::post().to(setup_post) );
 }
 
-pub async fn home(app: web::Data<Mutex<rpublish::RPublishApp>>) -> HttpResponse {
-    // Aquire app reference
-    let mut _app = app.lock().unwrap();
-
+pub async fn home() -> HttpResponse {
     HttpResponse::TemporaryRedirect()
         .header("Location", "/auth/login")
         .finish()
 }
 
-pub async fn login(req: HttpRequest, app: web::Data<Mutex<rpublish::RPublishApp>>) -> impl Responder {
-    // Aquire app reference
-    let app = app.lock().unwrap();
-
+pub async fn login(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>
+) -> impl Responder {
+    if app.identity_manager.setup_pending() {
+        return HttpResponse::TemporaryRedirect()
+            .header("Location", "/auth/setup")
+            .finish()
+    }
     if let Some(sessid_cookie) = req.cookie("SESSID") {
-        if app.identity_manager.sessions.validate(&sessid_cookie.value().to_string())
+        if app.identity_manager.sessions.validate(sessid_cookie.value())
         {
             return HttpResponse::TemporaryRedirect()
                 .header("Location", "/dashboard")
@@ -36,96 +48,181 @@ pub async fn login(req: HttpRequest, app: web::Data<Mutex<rpublish::RPublishApp>
         }
     }
 
-    match fs::read_to_string("assets/templates/login.html") {
-        Ok(login_template) => HttpResponse::Ok().body(login_template),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to read template"),
-    }
+    let mut context = Context::new();
+    context.insert("title", "Login");
+    templates.page("login.html", &context)
 }
 
 pub async fn login_post(
     req: HttpRequest,
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+    app: web::Data<rpublish::RPublishApp>, 
     form: web::Form<LoginFormData>
-) -> impl Responder {
-    let mut app = app.lock().unwrap();
-    let remote_ip = req.connection_info().remote_addr().unwrap_or_default().to_string();
+) -> Result<HttpResponse, AppError> {
+    let remote_ip = remote_ip(&req);
 
     if let Some(sessid_cookie) = req.cookie("SESSID") {
-        if app.identity_manager.sessions.validate(&sessid_cookie.value().to_string())
+        if app.identity_manager.sessions.validate(sessid_cookie.value())
         {
-            return HttpResponse::Forbidden().header("Location", "/dashboard").finish()
+            return Ok(HttpResponse::Forbidden().header("Location", "/dashboard").finish())
         }
     }
 
-    let mut login_data = String::new();
-    login_data.push_str(form.username.as_str());
-    login_data.push_str(form.password.as_str());
-
-    match app.identity_manager.users.get(form.username.as_str()) {
-        Ok(user) => {
-            match user.authenticate(form.password.as_str()) {
-                Ok(()) => {
-                    let uuid = Uuid::new_v4().to_simple();
-                    let uuid2 = Uuid::new_v4().to_simple();
-
-                    let sessid = format!("{}{}", uuid, uuid2);
-
-                    app.identity_manager.sessions.create(
-                        String::from(&sessid), 
-                        String::from(&form.username), 
-                        remote_ip
-                    );
-
-                    let cookie = Cookie::build("SESSID", sessid)
-                        .path("/")
-                        .secure(true)
-                        .http_only(true)
-                        .finish();
-
-                    HttpResponse::Found()
-                        .cookie(cookie)
-                        .header(http::header::LOCATION, "/dashboard")
-                        .finish().into_body()
-                },
-                Err(_) => HttpResponse::Unauthorized().body("Invalid credentials"),
-            }
+    // Password hashing and saving the session block, run them off the async threads
+    let username = form.username.to_owned();
+    let audit_ip = remote_ip.to_owned();
+    let audit_app = app.clone();
+    let login = web::block(move || {
+        let users = app.identity_manager.users.read_or_recover();
+        let user = users.get(form.username.as_str())?;
+        user.authenticate(form.password.as_str())?;
+
+        let uuid = Uuid::new_v4().to_simple();
+        let uuid2 = Uuid::new_v4().to_simple();
+
+        let sessid = format!("{}{}", uuid, uuid2);
+
+        app.identity_manager.sessions.create(
+            String::from(&sessid), 
+            String::from(&form.username), 
+            remote_ip
+        )?;
+        Ok::<String, IdentityError>(sessid)
+    }).await;
+
+    match login {
+        Ok(sessid) => {
+            audit_app.audit.record(AuditAction::Login, &username, Some(&audit_ip), None, None);
+            let cookie = Cookie::build("SESSID", sessid)
+                .path("/")
+                .secure(true)
+                .http_only(true)
+                .finish();
+
+            Ok(HttpResponse::Found()
+                .cookie(cookie)
+                .header(http::header::LOCATION, "/dashboard")
+                .finish().into_body())
         },
-        Err(_) => {
-            HttpResponse::Unauthorized().body("Invalid credentials")
+        // The credentials were right, the session couldn't be saved
+        Err(BlockingError::Error(error)) if matches!(error.kind(), IdentityErrorKind::Storage(_)) => Err(error.into()),
+        Err(error) => {
+            let reason = match &error {
+                BlockingError::Error(error) => error.to_string(),
+                BlockingError::Canceled => String::from("canceled"),
+            };
+            audit_app.audit.record(AuditAction::LoginFailed, &username, Some(&audit_ip), None, Some(&reason));
+            audit_app.metrics.record_failed_login();
+            // The reason is only audited, it would tell which users exist
+            Err(AppError::Unauthorized(String::from("Invalid credentials")))
         },
     }
 }
 
-pub async fn logout(req: HttpRequest, app: web::Data<Mutex<rpublish::RPublishApp>>) -> impl Responder {
-    // Aquire app reference
-    let mut app = app.lock().unwrap();
-
+pub async fn logout(req: HttpRequest, app: web::Data<rpublish::RPublishApp>) -> impl Responder {
     match req.cookie("SESSID") {
         Some(sessid_cookie) => {
             let sessid = sessid_cookie.value().to_string();
             if app.identity_manager.sessions.validate(&sessid)
             {
-                app.identity_manager.sessions.invalidate(&sessid);
+                let username = app.identity_manager.sessions.get_user(&sessid).unwrap_or_default();
+                app.audit.record(AuditAction::Logout, &username, Some(&remote_ip(&req)), None, None);
+                // Removed from memory even when the save fails, the error is logged
+                let _ = web::block(move || app.identity_manager.sessions.invalidate(&sessid)).await;
 
-                return HttpResponse::TemporaryRedirect()
+                HttpResponse::TemporaryRedirect()
                     .header("Location", "/dashboard")
                     .finish()
             } else {
-                return HttpResponse::TemporaryRedirect()
-                .header("Location", "/auth/login")
-                .finish()
+                HttpResponse::TemporaryRedirect()
+                    .header("Location", "/auth/login")
+                    .finish()
             }
         },
         None => {
-            return HttpResponse::TemporaryRedirect()
+            HttpResponse::TemporaryRedirect()
                 .header("Location", "/auth/login")
                 .finish()
         },
     }
 }
 
+// First run wizard, only available until the first admin is created
+pub async fn setup(
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    query: web::Query<SetupQuery>
+) -> Result<HttpResponse, AppError> {
+    if !app.identity_manager.setup_pending() {
+        return Err(setup_completed());
+    }
+    Ok(setup_page(&templates, query.token.as_deref().unwrap_or_default(), None))
+}
+
+fn setup_completed() -> AppError {
+    AppError::NotFound(String::from("Setup already completed"))
+}
+
+pub async fn setup_post(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    form: web::Form<SetupFormData>
+) -> Result<HttpResponse, AppError> {
+    if !app.identity_manager.setup_pending() {
+        return Err(setup_completed());
+    }
+    let form = form.into_inner();
+    if form.username.trim().is_empty() || form.password.trim().is_empty() {
+        return Ok(setup_page(&templates, &form.token, Some("Username and password are required")));
+    }
+    if form.password != form.password_repeat {
+        return Ok(setup_page(&templates, &form.token, Some("Passwords don't match")));
+    }
+
+    // Password hashing and saving the users block
+    let token = form.token.to_owned();
+    let username = form.username.to_owned();
+    let audit_app = app.clone();
+    let setup = web::block(move || {
+        app.identity_manager.complete_setup(&form.token, &form.username, &form.password)
+    }).await;
+
+    match setup {
+        Ok(_) => {
+            audit_app.audit.record(AuditAction::UserCreate, &username, Some(&remote_ip(&req)), Some(&username), Some("admin from the setup wizard"));
+            Ok(HttpResponse::Found()
+                .header(http::header::LOCATION, "/auth/login")
+                .finish())
+        },
+        // Shown in the form so the admin can try again
+        Err(BlockingError::Error(error)) => Ok(setup_page(&templates, &token, Some(&error.to_string()))),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn setup_page(templates: &Templates, token: &str, error: Option<&str>) -> HttpResponse {
+    let mut context = Context::new();
+    context.insert("title", "Setup");
+    context.insert("token", token);
+    context.insert("error", &error);
+    templates.page("setup.html", &context)
+}
+
 #[derive(Deserialize)]
 pub struct LoginFormData {
     username: String,
     password: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetupQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetupFormData {
+    token: String,
+    username: String,
+    password: String,
+    password_repeat: String,
 }
\ No newline at end of file
@@ -1,14 +1,29 @@
 use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, http, web};
 use uuid::Uuid;
-use std::{fs, sync::{Mutex}};
-use serde::{Serialize, Deserialize};
+use std::sync::{Mutex, RwLock};
+use tera::Context;
+use serde::{Deserialize};
 use crate::rpublish::{self};
+use crate::config::Config;
+use crate::locks::{MutexRecover, RwLockRecover};
+use crate::settings::SiteSettings;
+use crate::error::AppError;
+use crate::handlers::remote_ip;
+use crate::rpublish::audit::{self, AuditAction, AuditFilter};
+use crate::rpublish::overview;
+use crate::rpublish::identity_manager::UserPermissions;
+use crate::templates::Templates;
+use crate::themes;
+use crate::rpublish::articles_manager::article::ArticleUpdate;
+use crate::rpublish::articles_manager::listing::{ArticleFilter, ArticleListing, ListingCursor, ListingSort, ListingStatus, SortField, SortOrder};
+use chrono::{DateTime, Utc};
 use serde_json::json;
 
 pub fn configure (cfg: &mut web::ServiceConfig)
 {
 	cfg .route("", web::get().to(dashboard))
         .route("/articles", web::get().to(articles))
+        .route("/stats", web::get().to(stats))
         .route("/settings", web::get().to(settings))
         .route("/article/new", web::get().to(new_article))
         .route("/article/edit/{article_id}", web::get().to(edit_article_view))
@@ -19,241 +34,621 @@ pub fn configure (cfg: &mut web::ServiceConfig)
         .route("/api/article/{article_id}/unpublish", web::post().to(api_unpublish_article))
         .route("/api/article/{article_id}/discard", web::post().to(api_discard_article_changes))
         .route("/api/article/{article_id}/delete", web::post().to(api_delete_article))
+        .route("/api/article/{article_id}/history", web::get().to(api_article_history))
+        .route("/api/history/push", web::post().to(api_push_history))
         
         .route("/api/articles/draft/{start_index}/{count}", web::get().to(api_list_draft_articles))
-        .route("/api/articles/published/{start_index}/{count}", web::get().to(api_list_published_articles));
+        .route("/api/articles/published/{start_index}/{count}", web::get().to(api_list_published_articles))
+        .route("/api/articles/search", web::get().to(api_search_articles))
+        .route("/api/articles/cache", web::get().to(api_articles_cache_stats))
+        .route("/api/overview", web::get().to(api_overview))
+        .route("/api/audit", web::get().to(api_audit_log))
+        .route("/api/stats/top", web::get().to(api_stats_top))
+        .route("/api/stats/trend", web::get().to(api_stats_trend))
+        .route("/api/settings", web::get().to(api_get_settings))
+        .route("/api/settings", web::put().to(api_update_settings))
+        .route("/api/themes", web::get().to(api_list_themes))
+        .route("/api/theme", web::put().to(api_set_theme));
 }
 
-pub async fn dashboard() -> impl Responder {
-    HttpResponse::Ok().body(get_dashboard("Dashboard", &String::from("dashboard")))
+pub async fn dashboard(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>
+) -> Result<HttpResponse, AppError> {
+    let admin = session_is_admin(&req, &app);
+    let overview_app = app.clone();
+    let mut context = Context::new();
+    let overview = web::block(move || Ok::<_, AppError>(overview::overview(&overview_app, admin))).await?;
+    context.insert("overview", &overview);
+    Ok(dashboard_page(&req, &app, &templates, "Dashboard", "dashboard", context))
 }
 
-pub async fn articles() -> impl Responder {
-    HttpResponse::Ok().body(get_dashboard("Articles", &String::from("articles")))
+pub async fn articles(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>
+) -> impl Responder {
+    dashboard_page(&req, &app, &templates, "Articles", "articles", Context::new())
 }
 
-pub async fn settings() -> impl Responder {
-    HttpResponse::Ok().body(get_dashboard("Settings",  &String::from("settings")))
+pub async fn stats(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>
+) -> impl Responder {
+    dashboard_page(&req, &app, &templates, "Statistics", "stats", Context::new())
 }
 
-pub async fn new_article(
+pub async fn settings(
     req: HttpRequest,
-    app: web::Data<Mutex<rpublish::RPublishApp>>
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>
 ) -> impl Responder {
-    let mut app = app.lock().unwrap();
-    let sessid = req.cookie("SESSID").unwrap().value().to_string();
+    let mut context = Context::new();
+    context.insert("is_admin", &session_is_admin(&req, &app));
+    dashboard_page(&req, &app, &templates, "Settings", "settings", context)
+}
 
+pub async fn new_article(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>
+) -> Result<HttpResponse, AppError> {
+    let username = session_username(&req, &app)?;
     let uuid = Uuid::new_v4().to_simple();
-    let username = app.identity_manager.sessions.get_user(&sessid).unwrap();
-    app.articles_manager.create(
-        uuid.to_string().as_str(), 
-        username.as_str()
-    );
-    // HttpResponse::Ok().body(get_dashboard("New Article",  &String::from("new_article")))
-    HttpResponse::Found()
+    let article_id = uuid.to_string();
+    let ip = remote_ip(&req);
+    web::block(move || {
+        app.articles_manager.create(article_id.as_str(), username.as_str())?;
+        app.audit.record(AuditAction::ArticleCreate, &username, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+
+    Ok(HttpResponse::Found()
         .header(http::header::LOCATION, format!("{}{}", "/dashboard/article/edit/", uuid) )
-        .finish().into_body()
+        .finish().into_body())
 }
 
 pub async fn edit_article_view(
-    app: web::Data<Mutex<rpublish::RPublishApp>>,
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
     info: web::Path<String>
 ) -> impl Responder {
-    let app = app.lock().unwrap();
     let article_id: String = info.into_inner();
-    match app.articles_manager.read_latest(&article_id) {
-        Some(_) => {
-            HttpResponse::Ok().body(get_dashboard(
-                format!("Edit: {}", article_id).as_str(), 
-                 &String::from("edit_article")
-            ))
-        },
-        None => {
-            HttpResponse::Found()
-                .header(http::header::LOCATION,  "/dashboard/articles/" )
-                .finish().into_body()
-        },
+    let article_exists = {
+        let app = app.clone();
+        let article_id = article_id.to_owned();
+        web::block(move || app.articles_manager.read_latest(&article_id).ok_or(())).await.is_ok()
+    };
+
+    if article_exists {
+        let mut context = Context::new();
+        context.insert("article_id", &article_id);
+        dashboard_page(&req, &app, &templates, &format!("Edit: {}", article_id), "edit_article", context)
+    } else {
+        HttpResponse::Found()
+            .header(http::header::LOCATION,  "/dashboard/articles/" )
+            .finish().into_body()
     }
 }
 
-fn get_dashboard(title: &str, section: &str) -> String {
-    match fs::read_to_string("assets/templates/dashboard.html") {
-        Ok(dashboard_template) => {
-            let dashboard_template = dashboard_template.replace(
-                "{{title}}", 
-                title
-            ).replace(
-                "{{dashboard_items}}", 
-                get_dashboard_items().as_str()
-            ).replace(
-                "{{section_content}}", 
-                get_dashboard_section(section).as_str()
-            );
-            dashboard_template
-        },
-        Err(_) => String::new(),
+// Render a dashboard section inside the dashboard layout
+fn dashboard_page(
+    req: &HttpRequest,
+    app: &rpublish::RPublishApp,
+    templates: &Templates,
+    title: &str,
+    section: &str,
+    mut context: Context
+) -> HttpResponse {
+    context.insert("title", title);
+    context.insert("section", section);
+    context.insert("username", &session_user(req, app));
+    templates.page(&format!("dashboard/{}.html", section), &context)
+}
+
+// User of the session, dashboard requests are only accepted with a valid session
+fn session_user(req: &HttpRequest, app: &rpublish::RPublishApp) -> String {
+    session_username(req, app).unwrap_or_default()
+}
+
+// For handlers that need the user, the session can be revoked after the login check
+fn session_username(req: &HttpRequest, app: &rpublish::RPublishApp) -> Result<String, AppError> {
+    req.cookie("SESSID")
+        .and_then(|sessid_cookie| app.identity_manager.sessions.get_user(sessid_cookie.value()))
+        .ok_or_else(AppError::session_ended)
+}
+
+// For admin only requests, the action names what is refused
+fn require_admin(req: &HttpRequest, app: &rpublish::RPublishApp, action: &str) -> Result<(), AppError> {
+    if session_is_admin(req, app) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden(format!("Only admins can {}", action)))
     }
 }
 
-fn get_dashboard_section(section: &str) -> String {
-    match fs::read_to_string(format!("assets/templates/dashboard/{}.html", section)) {
-        Ok(section_template) => section_template,
-        Err(_) => String::new(),
+// Admin only sections, the session user needs the admin permission
+fn session_is_admin(req: &HttpRequest, app: &rpublish::RPublishApp) -> bool {
+    let username = session_user(req, app);
+    let users = app.identity_manager.users.read_or_recover();
+    users.get(&username)
+        .map(|user| user.permissions().contains(&UserPermissions::Admin))
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+pub struct ListingQuery {
+    #[serde(default)]
+    sort: SortField,
+    #[serde(default)]
+    order: SortOrder,
+    after: Option<String>
+}
+
+impl ListingQuery {
+    fn listing_sort(&self) -> ListingSort {
+        ListingSort {
+            field: self.sort,
+            order: self.order
+        }
+    }
+
+    fn cursor(&self) -> Result<Option<ListingCursor>, AppError> {
+        parse_cursor(self.sort, self.after.as_deref())
     }
 }
 
-fn get_dashboard_items() -> String {
-    match fs::read_to_string("assets/templates/dashboard_sidebar_items.html") {
-        Ok(items_template) =>  items_template,
-        Err(_) => String::new(),
+fn parse_cursor(sort: SortField, after: Option<&str>) -> Result<Option<ListingCursor>, AppError> {
+    match after {
+        Some(after) => ListingCursor::parse(sort, after)
+            .map(Some)
+            .ok_or_else(|| AppError::BadRequest(String::from("Invalid listing cursor"))),
+        None => Ok(None),
     }
 }
 
-fn api_list_draft_articles (
-    app: web::Data<Mutex<rpublish::RPublishApp>>,
-    info: web::Path<(usize, usize)>
-) -> HttpResponse {
-    let mut  app = app.lock().unwrap();
-    let limits = info.into_inner();
-    let result = app.articles_manager.list_draft_articles(limits.0, limits.1);
+fn listing_response(listing: ArticleListing<'_>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "articles": listing.articles,
+        "total": listing.total,
+        "next_cursor": listing.next_cursor
+    }))
+}
 
-    let json_response = json!({
-        "articles": result.0,
-        "total": result.1
-    });
+async fn api_list_draft_articles (
+    app: web::Data<rpublish::RPublishApp>,
+    info: web::Path<(usize, usize)>,
+    query: web::Query<ListingQuery>
+) -> Result<HttpResponse, AppError> {
+    let limits = info.into_inner();
+    let cursor = query.cursor()?;
 
-    HttpResponse::Ok().json(json_response)
+    Ok(listing_response(app.articles_manager.index().list_draft_articles(
+        limits.0, limits.1, query.listing_sort(), cursor.as_ref()
+    )))
 }
 
-fn api_list_published_articles (
-    app: web::Data<Mutex<rpublish::RPublishApp>>,
-    info: web::Path<(usize, usize)>
-) -> HttpResponse {
-    let mut  app = app.lock().unwrap();
+async fn api_list_published_articles (
+    app: web::Data<rpublish::RPublishApp>,
+    info: web::Path<(usize, usize)>,
+    query: web::Query<ListingQuery>
+) -> Result<HttpResponse, AppError> {
     let limits = info.into_inner();
-    let result = app.articles_manager.list_published_articles(limits.0, limits.1);
+    let cursor = query.cursor()?;
 
-    let json_response = json!({
-        "articles": result.0,
-        "total": result.1
-    });
+    Ok(listing_response(app.articles_manager.index().list_published_articles(
+        limits.0, limits.1, query.listing_sort(), cursor.as_ref()
+    )))
+}
 
-    HttpResponse::Ok().json(json_response)
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    start_index: usize,
+    #[serde(default = "default_search_count")]
+    count: usize,
+    #[serde(default)]
+    sort: SortField,
+    #[serde(default)]
+    order: SortOrder,
+    after: Option<String>,
+    status: Option<ListingStatus>,
+    author: Option<String>,
+    tag: Option<String>,
+    title: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    updated_after: Option<DateTime<Utc>>,
+    updated_before: Option<DateTime<Utc>>
 }
 
-fn api_get_article (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
-    info: web::Path<String>
-) -> HttpResponse {
-    let app = app.lock().unwrap();
-    let article_id: String = info.into_inner();
+fn default_search_count() -> usize {
+    30
+}
 
-    match app.articles_manager.read_latest(&article_id) {
-        Some(article) => {
-            HttpResponse::Ok().json(json!({
-                "article": article.0,
-                "status": article.1,
-                "published": article.2,
-                "published_date": article.3
-            }))
-        },
-        None => {
-            HttpResponse::NotFound().finish()
-        },
+impl SearchQuery {
+    fn filter(&self) -> ArticleFilter {
+        // Empty text fields come from blank inputs, treat them as not set
+        let non_empty = |value: &Option<String>| value.to_owned().filter(|value| !value.trim().is_empty());
+        ArticleFilter {
+            status: self.status,
+            author: non_empty(&self.author),
+            tag: non_empty(&self.tag),
+            title: non_empty(&self.title),
+            created_after: self.created_after,
+            created_before: self.created_before,
+            updated_after: self.updated_after,
+            updated_before: self.updated_before
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct ArticleUpdate {
-    title: String,
-    data: String
+async fn api_search_articles (
+    app: web::Data<rpublish::RPublishApp>,
+    query: web::Query<SearchQuery>
+) -> Result<HttpResponse, AppError> {
+    let sort = ListingSort {
+        field: query.sort,
+        order: query.order
+    };
+    let cursor = parse_cursor(query.sort, query.after.as_deref())?;
+
+    Ok(listing_response(app.articles_manager.index().query_articles(
+        &query.filter(), query.start_index, query.count, sort, cursor.as_ref()
+    )))
+}
+
+async fn api_articles_cache_stats (
+    app: web::Data<rpublish::RPublishApp>
+) -> HttpResponse {
+    HttpResponse::Ok().json(app.articles_manager.cache_stats())
+}
+
+async fn api_get_article (
+    app: web::Data<rpublish::RPublishApp>, 
+    info: web::Path<String>
+) -> Result<HttpResponse, AppError> {
+    let article_id: String = info.into_inner();
+
+    let article = web::block(move || {
+        app.articles_manager.read_latest(&article_id)
+            .ok_or_else(|| AppError::NotFound(format!("Article {} not found", article_id)))
+    }).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "article": article.0,
+        "status": article.1,
+        "published": article.2,
+        "published_date": article.3
+    })))
 }
 
-fn api_update_article (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+async fn api_update_article (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>, 
     info: web::Path<String>,
     article_update: web::Json<ArticleUpdate>
-) -> HttpResponse {
-    let mut app = app.lock().unwrap();
+) -> Result<HttpResponse, AppError> {
     let article_id: String = info.into_inner();
+    let mut article_update = article_update.into_inner();
+    article_update.validate()?;
 
-    match app.articles_manager.update(&article_id, &article_update.title, &article_update.data) {
-        Ok(_) => {
-            HttpResponse::Ok().finish()
-        },
-        Err(_) => {
-            HttpResponse::NotFound().finish()
-        },
-    }
+    let editor = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+
+    web::block(move || {
+        app.articles_manager.update(&article_id, &article_update, &editor)?;
+        app.audit.record(AuditAction::ArticleUpdate, &editor, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-fn api_publish_article (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+async fn api_publish_article (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>, 
     info: web::Path<String>
-) -> HttpResponse {
-    let mut app = app.lock().unwrap();
+) -> Result<HttpResponse, AppError> {
     let article_id: String = info.into_inner();
 
-    match app.articles_manager.publish(&article_id) {
-        Ok(_) => {
-            HttpResponse::Ok().finish()
-        },
-        Err(_) => {
-            HttpResponse::NotFound().finish()
-        },
-    }
+    let editor = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+
+    web::block(move || {
+        app.articles_manager.publish(&article_id, &editor)?;
+        app.audit.record(AuditAction::ArticlePublish, &editor, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-fn api_unpublish_article (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+async fn api_unpublish_article (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>, 
     info: web::Path<String>
-) -> HttpResponse {
-    let mut app = app.lock().unwrap();
+) -> Result<HttpResponse, AppError> {
     let article_id: String = info.into_inner();
 
-    match app.articles_manager.unpublish(&article_id) {
-        Ok(_) => {
-            HttpResponse::Ok().finish()
-        },
-        Err(_) => {
-            HttpResponse::NotFound().finish()
-        },
-    }
+    let editor = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+
+    web::block(move || {
+        app.articles_manager.unpublish(&article_id, &editor)?;
+        app.audit.record(AuditAction::ArticleUnpublish, &editor, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-fn api_discard_article_changes (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+async fn api_discard_article_changes (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>, 
     info: web::Path<String>
-) -> HttpResponse {
-    let mut app = app.lock().unwrap();
+) -> Result<HttpResponse, AppError> {
     let article_id: String = info.into_inner();
 
-    match app.articles_manager.discard_changes(&article_id) {
-        Ok(_) => {
-            HttpResponse::Ok().finish()
-        },
-        Err(error) => {
-            println!("{:?}", error);
-            HttpResponse::NotFound().body("Failed to discard article")
-        },
-    }
+    let editor = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+
+    web::block(move || {
+        app.articles_manager.discard_changes(&article_id, &editor)?;
+        app.audit.record(AuditAction::ArticleDiscard, &editor, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-fn api_delete_article (
-    app: web::Data<Mutex<rpublish::RPublishApp>>, 
+async fn api_delete_article (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>, 
     info: web::Path<String>
-) -> HttpResponse {
-    let mut app = app.lock().unwrap();
+) -> Result<HttpResponse, AppError> {
     let article_id: String = info.into_inner();
 
-    match app.articles_manager.delete(&article_id) {
-        Ok(_) => {
-            HttpResponse::Ok().finish()
-        },
-        Err(error) => {
-            println!("{:?}", error);
-            HttpResponse::NotFound().body("Failed to delete article")
-        },
+    let editor = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+
+    web::block(move || {
+        app.articles_manager.delete(&article_id, &editor)?;
+        app.audit.record(AuditAction::ArticleDelete, &editor, Some(&ip), Some(&article_id), None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn api_article_history (
+    app: web::Data<rpublish::RPublishApp>,
+    info: web::Path<String>
+) -> Result<HttpResponse, AppError> {
+    let article_id: String = info.into_inner();
+
+    let entries = web::block(move || match app.articles_manager.history() {
+        Some(history) => history.log(&article_id).map_err(history_error),
+        None => Err(history_disabled()),
+    }).await?;
+    Ok(HttpResponse::Ok().json(json!({ "history": entries })))
+}
+
+async fn api_push_history (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &app, "push the article history")?;
+    let username = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+    web::block(move || {
+        match app.articles_manager.history() {
+            Some(history) => history.push().map_err(history_error)?,
+            None => return Err(history_disabled()),
+        }
+        app.audit.record(AuditAction::HistoryPush, &username, Some(&ip), None, None);
+        Ok(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn history_disabled() -> AppError {
+    AppError::NotFound(String::from("Article history is disabled"))
+}
+
+// A missing remote and invalid ids are told to the client, failed git commands are logged
+fn history_error(error: std::io::Error) -> AppError {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => AppError::NotFound(error.to_string()),
+        std::io::ErrorKind::InvalidInput => AppError::BadRequest(error.to_string()),
+        _ => AppError::Io(error),
     }
 }
 
+// Article counts, recent activity, disk usage and uptime shown in the dashboard home
+async fn api_overview (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>
+) -> Result<HttpResponse, AppError> {
+    let admin = session_is_admin(&req, &app);
+    let overview = web::block(move || Ok::<_, AppError>(overview::overview(&app, admin))).await?;
+    Ok(HttpResponse::Ok().json(overview))
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat
+}
+
+fn default_audit_limit() -> usize {
+    100
+}
+
+// Filters are `actor`, `action`, `target`, `ip` (prefix), `since` and `until` (rfc3339)
+// and `limit`, `format=csv` downloads the matching entries as a spreadsheet
+async fn api_audit_log (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    filter: web::Query<AuditFilter>,
+    export: web::Query<ExportQuery>
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &app, "read the audit log")?;
+    let mut filter = filter.into_inner();
+    // Exports take every matching entry unless a limit is given
+    if filter.limit.is_none() && export.format == ExportFormat::Json {
+        filter.limit = Some(default_audit_limit());
+    }
+
+    let entries = web::block(move || app.audit.query(&filter)).await?;
+    Ok(match export.format {
+        ExportFormat::Json => HttpResponse::Ok().json(json!({ "entries": entries })),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .header(http::header::CONTENT_DISPOSITION, "attachment; filename=\"audit.csv\"")
+            .body(audit::to_csv(&entries)),
+    })
+}
+
+// Longest period read at once, a file per day
+const STATS_MAX_DAYS: u32 = 366;
+
+fn default_stats_days() -> u32 {
+    30
+}
+
+fn default_stats_limit() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(default = "default_stats_days")]
+    days: u32,
+    #[serde(default = "default_stats_limit")]
+    limit: usize,
+    // Trend of a single article, the whole site without it
+    article: Option<String>
+}
+
+// Most viewed articles and referrer domains of the last `days`
+async fn api_stats_top (
+    app: web::Data<rpublish::RPublishApp>,
+    query: web::Query<StatsQuery>
+) -> Result<HttpResponse, AppError> {
+    let days = query.days.clamp(1, STATS_MAX_DAYS);
+    let limit = query.limit;
+    let stats_app = app.clone();
+    let (top, referrers) = web::block(move || Ok::<_, AppError>(stats_app.stats.top(days, limit))).await?;
+
+    let index = app.articles_manager.index();
+    let articles: Vec<serde_json::Value> = top.iter()
+        .map(|article| json!({
+            "id": article.id,
+            // Articles no longer published have no title
+            "title": index.published_metadata(&article.id).map(|metadata| metadata.title.to_owned()),
+            "views": article.views,
+            "visitors": article.visitors
+        }))
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "days": days,
+        "articles": articles,
+        "referrers": referrers
+    })))
+}
+
+// Views and visitors by day of the last `days`
+async fn api_stats_trend (
+    app: web::Data<rpublish::RPublishApp>,
+    query: web::Query<StatsQuery>
+) -> Result<HttpResponse, AppError> {
+    let query = query.into_inner();
+    let days = query.days.clamp(1, STATS_MAX_DAYS);
+    let trend = web::block(move || Ok::<_, AppError>(app.stats.trend(days, query.article.as_deref()))).await?;
+    Ok(HttpResponse::Ok().json(json!({ "days": trend })))
+}
+
+async fn api_list_themes (
+    templates: web::Data<Templates>
+) -> HttpResponse {
+    let themes = web::block(|| Ok::<_, ()>(themes::list_themes())).await.unwrap_or_default();
+    let themes: Vec<serde_json::Value> = themes.into_iter()
+        .map(|(theme_id, manifest)| json!({
+            "id": theme_id,
+            "name": manifest.name,
+            "description": manifest.description,
+            "version": manifest.version,
+            "author": manifest.author
+        }))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "active": templates.theme(),
+        "themes": themes
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ThemeSelection {
+    theme: String
+}
+
+async fn api_set_theme (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    config: web::Data<Mutex<Config>>,
+    templates: web::Data<Templates>,
+    selection: web::Json<ThemeSelection>
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &app, "change the theme")?;
+    let username = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+    let theme = selection.into_inner().theme;
+    // Compiling the templates and saving the config read and write files
+    web::block(move || {
+        if let Err(error) = templates.set_theme(&theme) {
+            log::error!("Failed to set theme {}: {:?}", theme, error);
+            return Err(AppError::BadRequest(format!("Theme {} can't be used: {}", theme, error)));
+        }
+        let mut config = config.lock_or_recover();
+        config.theme = theme;
+        config.save();
+        app.audit.record(AuditAction::SettingsUpdate, &username, Some(&ip), None, Some(&format!("theme {}", config.theme)));
+        Ok(())
+    }).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn api_get_settings (
+    settings: web::Data<RwLock<SiteSettings>>
+) -> HttpResponse {
+    let settings = settings.read_or_recover().clone();
+    HttpResponse::Ok().json(settings)
+}
+
+// Replace the site settings, every invalid field is answered with its error
+async fn api_update_settings (
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    settings: web::Data<RwLock<SiteSettings>>,
+    update: web::Json<SiteSettings>
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &app, "change the site settings")?;
+    let mut update = update.into_inner();
+    update.validate()?;
+    let username = session_username(&req, &app)?;
+    let ip = remote_ip(&req);
+    let saved = update.clone();
+    web::block(move || {
+        saved.save()?;
+        *settings.write_or_recover() = saved;
+        app.audit.record(AuditAction::SettingsUpdate, &username, Some(&ip), None, None);
+        Ok::<(), AppError>(())
+    }).await?;
+    Ok(HttpResponse::Ok().json(update))
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use actix_web::{HttpRequest, HttpResponse, Responder, http, web};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::locks::MutexRecover;
+use crate::rpublish::{self, metrics};
+use crate::rpublish::identity_manager::constant_time_eq;
+
+// Directories the server writes to, checked by /readyz
+const WRITABLE_DIRS: [&str; 5] = ["data", "data/articles", "data/public", "data/logs", "data/cache"];
+
+pub fn configure (cfg: &mut web::ServiceConfig)
+{
+	cfg.route("/healthz", web::get().to(healthz))
+       .route("/readyz", web::get().to(readyz))
+       .route("/metrics", web::get().to(metrics));
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: String,
+    ok: bool,
+    error: Option<String>
+}
+
+impl Check {
+    fn new(name: &str, result: Result<(), String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: result.is_ok(),
+            error: result.err()
+        }
+    }
+}
+
+// The process is up and answering
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body("ok")
+}
+
+// Write and remove a file, a full disk or a read only mount fail here
+fn check_writable(dir: &str) -> Result<(), String> {
+    let probe = Path::new(dir).join(format!(".readyz-{}", Uuid::new_v4().to_simple()));
+    fs::write(&probe, b"ok").map_err(|error| error.to_string())?;
+    fs::remove_file(&probe).map_err(|error| error.to_string())
+}
+
+// Poisoned locks are recovered here, otherwise an instance taken out of the load balancer
+// would get no request to recover them and stay unready
+fn check_locks(app: &rpublish::RPublishApp) -> Result<(), String> {
+    if app.identity_manager.is_poisoned() || app.articles_manager.is_poisoned() {
+        app.identity_manager.recover();
+        app.articles_manager.recover();
+    }
+    let mut poisoned = Vec::new();
+    if app.identity_manager.is_poisoned() {
+        poisoned.push("users or sessions");
+    }
+    if app.articles_manager.is_poisoned() {
+        poisoned.push("articles");
+    }
+    if poisoned.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Poisoned {} lock", poisoned.join(" and ")))
+    }
+}
+
+// The data directories are writable, the users and articles are loaded and no lock was
+// poisoned by a panic, answered with 503 otherwise so the load balancer stops sending requests
+pub async fn readyz(app: web::Data<rpublish::RPublishApp>) -> impl Responder {
+    let checks = web::block(move || {
+        let mut checks: Vec<Check> = WRITABLE_DIRS.iter()
+            .map(|dir| Check::new(&format!("writable {}", dir), check_writable(dir)))
+            .collect();
+        checks.push(Check::new("locks", check_locks(&app)));
+        // The stores are read before the server starts, a setup still waiting for the admin isn't ready
+        let users = if app.identity_manager.setup_pending() {
+            Err(String::from("Waiting for the first admin"))
+        } else {
+            Ok(())
+        };
+        checks.push(Check::new("users", users));
+        Ok::<_, ()>(checks)
+    }).await.unwrap_or_default();
+
+    let ready = !checks.is_empty() && checks.iter().all(|check| check.ok);
+    let body = serde_json::json!({ "ready": ready, "checks": checks });
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// Allowed when no restriction is configured, metrics_token is taken as a bearer token
+fn metrics_allowed(req: &HttpRequest, config: &Config) -> bool {
+    if config.metrics_localhost_only && !req.peer_addr().is_some_and(|address| address.ip().is_loopback()) {
+        return false;
+    }
+    // Read apart from the config so the token from the environment is never saved with it
+    let token = std::env::var("RPUBLISH_METRICS_TOKEN").ok().or_else(|| config.metrics_token.to_owned());
+    match token.as_deref().filter(|token| !token.is_empty()) {
+        Some(token) => req.headers().get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes())),
+        None => true,
+    }
+}
+
+// Prometheus text format
+pub async fn metrics(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    config: web::Data<Mutex<Config>>
+) -> Result<HttpResponse, AppError> {
+    let allowed = {
+        let config = config.lock_or_recover();
+        metrics_allowed(&req, &config)
+    };
+    if !allowed {
+        return Err(AppError::Forbidden(String::from("Metrics are restricted")));
+    }
+    let metrics = web::block(move || Ok::<_, AppError>(metrics::render(&app))).await?;
+    Ok(HttpResponse::Ok().content_type("text/plain; version=0.0.4; charset=utf-8").body(metrics))
+}
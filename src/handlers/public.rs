@@ -1,15 +1,155 @@
-use actix_web::{Responder, web};
-use std::sync::Mutex;
-use crate::rpublish;
+use std::sync::RwLock;
+use actix_files::NamedFile;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, http, web};
+use crate::error::AppError;
+use crate::handlers::remote_ip;
+use crate::locks::RwLockRecover;
+use crate::rpublish::{self, stats};
+use crate::settings::SiteSettings;
+use crate::site::{self, SiteLinks};
+use crate::templates::Templates;
+use crate::themes;
 
 pub fn configure (cfg: &mut web::ServiceConfig)
 {
-	cfg.route("", web::get().to(home));
+	cfg.route("/", web::get().to(home))
+       .route("/page/{page}", web::get().to(home_page))
+       .route("/tag/{tag}", web::get().to(tag))
+       .route("/tag/{tag}/page/{page}", web::get().to(tag_page))
+       .route("/feed.xml", web::get().to(feed))
+       .route("/sitemap.xml", web::get().to(sitemap))
+       .route("/article/{article_id}", web::get().to(article))
+       .route("/theme/{asset:.*}", web::get().to(theme_asset));
 }
 
-pub async fn home(app: web::Data<Mutex<rpublish::RPublishApp>>) -> impl Responder {
-    // Aquire app reference
-    let mut _app = app.lock().unwrap();
+pub async fn home(
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    settings: web::Data<RwLock<SiteSettings>>
+) -> impl Responder {
+    listing(&app, &templates, &settings, None, 1)
+}
+
+pub async fn home_page(
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    settings: web::Data<RwLock<SiteSettings>>,
+    info: web::Path<usize>
+) -> impl Responder {
+    listing(&app, &templates, &settings, None, info.into_inner())
+}
+
+pub async fn tag(
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    settings: web::Data<RwLock<SiteSettings>>,
+    info: web::Path<String>
+) -> impl Responder {
+    listing(&app, &templates, &settings, Some(&info.into_inner()), 1)
+}
 
-    "home".to_string()
-}
\ No newline at end of file
+pub async fn tag_page(
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    settings: web::Data<RwLock<SiteSettings>>,
+    info: web::Path<(String, usize)>
+) -> impl Responder {
+    let (tag_slug, page) = info.into_inner();
+    listing(&app, &templates, &settings, Some(&tag_slug), page)
+}
+
+// Page of the published articles, newest first, or of the ones tagged with tag_slug
+fn listing(
+    app: &rpublish::RPublishApp,
+    templates: &Templates,
+    settings: &RwLock<SiteSettings>,
+    tag_slug: Option<&str>,
+    page: usize
+) -> Result<HttpResponse, AppError> {
+    let settings = settings.read_or_recover().clone();
+    let articles = app.articles_manager.index().published_by_date();
+    let context = match tag_slug {
+        Some(tag_slug) => match site::tags(&articles).remove(tag_slug) {
+            Some((name, tagged)) => site::listing_context(&SiteLinks::Server, &settings, &tagged, Some((tag_slug, &name)), page, true),
+            None => None,
+        },
+        None => site::listing_context(&SiteLinks::Server, &settings, &articles, None, page, true),
+    };
+    match context {
+        Some(context) => Ok(templates.page("theme/index.html", &context)),
+        None => Err(AppError::NotFound(String::from("Page not found"))),
+    }
+}
+
+pub async fn feed(
+    app: web::Data<rpublish::RPublishApp>,
+    settings: web::Data<RwLock<SiteSettings>>
+) -> impl Responder {
+    let settings = settings.read_or_recover().clone();
+    let links = SiteLinks::base_url(&settings.public_base_url());
+    let articles = app.articles_manager.index().published_by_date();
+    HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(site::feed_xml(&links, &settings, &articles))
+}
+
+pub async fn sitemap(
+    app: web::Data<rpublish::RPublishApp>,
+    settings: web::Data<RwLock<SiteSettings>>
+) -> impl Responder {
+    let settings = settings.read_or_recover().clone();
+    let links = SiteLinks::base_url(&settings.public_base_url());
+    let articles = app.articles_manager.index().published_by_date();
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(site::sitemap_xml(&links, &settings, &articles))
+}
+
+pub async fn article(
+    req: HttpRequest,
+    app: web::Data<rpublish::RPublishApp>,
+    templates: web::Data<Templates>,
+    settings: web::Data<RwLock<SiteSettings>>,
+    info: web::Path<String>
+) -> Result<HttpResponse, AppError> {
+    let article_id: String = info.into_inner();
+    let published_id = article_id.to_owned();
+    let app_stats = app.clone();
+
+    let cached = web::block(move || {
+        app.articles_manager.read_published(&published_id)
+            .ok_or_else(|| AppError::NotFound(String::from("Article not found")))
+    }).await?;
+    count_view(&req, &app_stats, &article_id);
+    let settings = settings.read_or_recover().clone();
+    let base_url = settings.public_base_url();
+    let context = site::article_context(&SiteLinks::Server, &settings, &article_id, &cached, &base_url, true);
+    Ok(templates.page("theme/article.html", &context))
+}
+
+// Views of visitors, editors reading with a session and bots are left out
+fn count_view(req: &HttpRequest, app: &rpublish::RPublishApp, article_id: &str) {
+    if let Some(sessid_cookie) = req.cookie("SESSID") {
+        if app.identity_manager.sessions.validate(sessid_cookie.value()) {
+            return;
+        }
+    }
+    let header = |name| req.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let user_agent = header(http::header::USER_AGENT);
+    if stats::is_bot(user_agent) {
+        return;
+    }
+    let referrer = stats::referrer_domain(header(http::header::REFERER), req.connection_info().host());
+    app.stats.record_view(article_id, &format!("{} {}", remote_ip(req), user_agent), referrer);
+}
+
+// Static files of the selected theme, missing files are taken from the default theme
+pub async fn theme_asset(
+    templates: web::Data<Templates>,
+    info: web::Path<String>
+) -> Result<NamedFile, AppError> {
+    match themes::asset_path(&templates.theme(), &info.into_inner()) {
+        Some(asset_path) => Ok(NamedFile::open(asset_path)?.use_last_modified(true)),
+        None => Err(AppError::NotFound(String::from("Theme file not found"))),
+    }
+}
@@ -1,22 +1,53 @@
-use std::fs::File;
+use std::fs::{File, OpenOptions, TryLockError};
 use std::{fs};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::{Error, ErrorKind, Result, Write};
 
-extern crate termion;
-use termion::{color};
+// Held by the server while it runs and by the commands that change users or sessions
+const DATA_LOCK_PATH: &str = "data/rpublish.lock";
 
 pub fn setup_system() -> Result<()> {
     let setup_dirs_complete = setup_directories_structure();
     if setup_dirs_complete {
         Ok(())
     }else{
-        Err(Error::new(ErrorKind::Other, "Checks for directory structure failed"))
+        Err(Error::other("Checks for directory structure failed"))
     }
 }
 
+// Only run by the server once it holds the data directory lock, a command line run could
+// otherwise move or remove the temporary files of a running server
+pub fn repair_interrupted_writes() {
+    log::info!("Checking interrupted writes");
+    for dir_path in ATOMIC_WRITE_DIRS {
+        repair_temp_files(Path::new(dir_path));
+    }
+}
+
+// Exclusive lock of the data directory, None while another process holds it. It is released
+// when the file is dropped or the process ends, a crashed server never leaves it taken
+pub fn lock_data_dir() -> Result<Option<File>> {
+    let lock = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(DATA_LOCK_PATH)?;
+    match lock.try_lock() {
+        Ok(_) => Ok(Some(lock)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(error)) => Err(error),
+    }
+}
+
+// The server writes its pid into the lock file so the commands can name it
+pub fn write_lock_pid(lock: &mut File) -> Result<()> {
+    lock.set_len(0)?;
+    write!(lock, "{}", std::process::id())?;
+    lock.sync_all()
+}
+
+pub fn read_lock_pid() -> Option<u32> {
+    fs::read_to_string(DATA_LOCK_PATH).ok()?.trim().parse().ok()
+}
+
 fn setup_directories_structure() -> bool{
-    println!("{}- Initializing directories", color::Fg(color::Yellow));
+    log::info!("Initializing directories");
     // Root directory
     if !graceful_mkdir("data") {return false;}
 
@@ -32,6 +63,7 @@ fn setup_directories_structure() -> bool{
     if !graceful_mkdir("data/articles") {return false;}
     if !graceful_mkdir("data/articles/published") {return false;}
     if !graceful_mkdir("data/articles/draft") {return false;}
+    if !graceful_mkdir("data/articles/journal") {return false;}
 
     if !graceful_mkdir("data/articles_trashcan") {return false;}
     if !graceful_mkdir("data/articles_trashcan/published") {return false;}
@@ -45,6 +77,9 @@ fn setup_directories_structure() -> bool{
     if !graceful_mkdir("data/cache/search") {return false;}
     if !graceful_mkdir("data/cache/stats") {return false;}
 
+    // Installed themes
+    if !graceful_mkdir("data/themes") {return false;}
+
     // System logs
     if !graceful_mkdir("data/logs") {return false;}
 
@@ -56,18 +91,18 @@ fn graceful_mkdir(dir_path: &str) -> bool {
     let current_path = current_path.as_path().display();
     let path = Path::new(dir_path);
     // Get the metadata attributes of a file/dir and check if it exists or something is wrong
-    match fs::metadata(&path) {
+    match fs::metadata(path) {
         Ok(attributes) => {
             if attributes.is_dir() {
                 if attributes.permissions().readonly() {
-                    println!("{}\t{}/{}: Is not writable{}", color::Fg(color::Red), current_path, path.display(), color::Fg(color::Reset));
+                    log::error!("{}/{}: Is not writable", current_path, path.display());
                     return false;
                 }
-                println!("{}\t{}/{}: OK{}", color::Fg(color::Green), current_path, path.display(), color::Fg(color::Reset));
+                log::debug!("{}/{}: OK", current_path, path.display());
                 true
             }
             else {
-                println!("{}\t{}/{}: Is not a directory{}", color::Fg(color::Red), current_path,path.display(), color::Fg(color::Reset));
+                log::error!("{}/{}: Is not a directory", current_path, path.display());
                 false
             }
         },
@@ -79,17 +114,17 @@ fn graceful_mkdir(dir_path: &str) -> bool {
                     let create_result = fs::create_dir(path);
                     match create_result {
                         Ok(_) => {
-                            println!("{}\t{}/{}: Created{}", color::Fg(color::Cyan), current_path,path.display(), color::Fg(color::Reset));
+                            log::info!("{}/{}: Created", current_path, path.display());
                             true
                         },
                         Err(create_error) =>  {
-                            println!("{}\t{}/{}: {}{}", color::Fg(color::Red), current_path,path.display(), create_error, color::Fg(color::Reset));
+                            log::error!("{}/{}: {}", current_path, path.display(), create_error);
                             false
                         }
                     }
                 },
                 _ => {
-                    println!("{}\tError not managed {}{}", color::Fg(color::Red), error, color::Fg(color::Reset));
+                    log::error!("Error not managed {}", error);
                     false
                 }
             }
@@ -105,44 +140,223 @@ pub fn write_json(file_path: &str, content: String) -> Result<()>
 
     // Create the path
     let path = Path::new(file_path);
-    
-    // Create the file, truncates if it exist
-    match File::create(path) {
-        Ok(mut file) => {
-            // Try to write the content
-            match file.write_all(content.as_bytes()) {
+    let temp_path = temp_path_for(path);
+
+    // Write a temporary file next to the target and move it over the target when complete,
+    // a crash leaves either the old or the new file but never a truncated one
+    match write_synced(&temp_path, content.as_bytes()) {
+        Ok(_) => {
+            match fs::rename(&temp_path, path) {
                 Ok(_) => {
-                    // Content written
-                    //println!("{} File Writed: {}/{}", color::Fg(color::Cyan), current_path, path.display());
+                    sync_parent_dir(path);
                     Ok(())
                 },
                 Err(error) => {
-                    // Pretty error, Cannot be written
+                    let _ = fs::remove_file(&temp_path);
                     Err(Error::new(
                         error.kind(), 
-                        format!("{}File cannot be written: {}/{}", color::Fg(color::Red), current_path, path.display())
+                        format!("File cannot be replaced: {}/{} {:?}", current_path, path.display(), error)
                     ))
                 }
             }
         },
         Err(error) => {
-            // Pretty error, Cannot be created
+            // Pretty error, Cannot be written
+            let _ = fs::remove_file(&temp_path);
             Err(Error::new(
                 error.kind(), 
-                format!("{}File: {}/{} Cannot be created {:?}", color::Fg(color::Red), current_path, path.display(), error)
+                format!("File cannot be written: {}/{} {:?}", current_path, path.display(), error)
             ))
         }
     }
 }
 
+// Move a file in a single step, the target is replaced if it exists
 pub fn move_file(origin_path: &str, target_path: &str) -> Result<()> {
-    match fs::copy(origin_path, target_path) {
+    let target = Path::new(target_path);
+    match fs::rename(origin_path, target) {
         Ok(_) => {
-            match fs::remove_file(origin_path) {
-                Ok(_) => Ok(()),
-                Err(error) => Err(error),
+            sync_parent_dir(Path::new(origin_path));
+            sync_parent_dir(target);
+            Ok(())
+        },
+        Err(error) => {
+            if error.kind() == ErrorKind::NotFound {
+                return Err(error);
             }
+            // Rename fails across filesystems, copy to a temporary file in the target directory instead
+            let temp_path = temp_path_for(target);
+            let content = fs::read(origin_path)?;
+            if let Err(error) = write_synced(&temp_path, &content).and_then(|_| fs::rename(&temp_path, target)) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(error);
+            }
+            sync_parent_dir(target);
+            fs::remove_file(origin_path)?;
+            sync_parent_dir(Path::new(origin_path));
+            Ok(())
         },
-        Err(error) => Err(error),
     }
-}
\ No newline at end of file
+}
+
+// Temporary files are named after their target, "users.json" is written to "users.json.tmp"
+const TEMP_SUFFIX: &str = ".tmp";
+
+// Directories of the json stores written with write_json and move_file. Only these are
+// repaired, uploads in data/public or the history repository may hold any .tmp file
+const ATOMIC_WRITE_DIRS: [&str; 10] = [
+    "data",
+    "data/auth",
+    "data/articles/draft",
+    "data/articles/published",
+    "data/articles/journal",
+    "data/articles_trashcan/draft",
+    "data/articles_trashcan/published",
+    "data/cache/metadata/draft",
+    "data/cache/metadata/published",
+    "data/cache/stats",
+];
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(TEMP_SUFFIX);
+    PathBuf::from(temp_path)
+}
+
+// Target of a temporary file left by write_json or move_file, they only write json files
+fn temp_target(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_str()?;
+    let target_name = file_name.strip_suffix(TEMP_SUFFIX)?;
+    match target_name.strip_suffix(".json") {
+        Some(stem) if !stem.is_empty() => Some(path.with_file_name(target_name)),
+        _ => None,
+    }
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+// Persist the directory entry after a rename, not supported on every platform
+fn sync_parent_dir(path: &Path) {
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+// Handle temporary files left in a directory by writes interrupted by a crash, sub directories
+// are not visited. A complete json file whose target is missing is moved into place, anything
+// else is removed as the target is intact
+fn repair_temp_files(dir_path: &Path) {
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let target = match temp_target(&path) {
+            Some(target) => target,
+            None => continue,
+        };
+        let is_complete = fs::read_to_string(&path)
+            .map(|content| serde_json::from_str::<serde_json::Value>(&content).is_ok())
+            .unwrap_or(false);
+
+        if is_complete && !target.exists() {
+            match fs::rename(&path, &target) {
+                Ok(_) => log::warn!("Recovered {} from an interrupted write", target.display()),
+                Err(error) => log::error!("Failed to recover {}: {}", target.display(), error),
+            }
+        } else {
+            match fs::remove_file(&path) {
+                Ok(_) => log::info!("Removed leftover temporary file {}", path.display()),
+                Err(error) => log::error!("Failed to remove {}: {}", path.display(), error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Empty directory for a test, removed again by the test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rpublish-helpers-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn temp_target_only_matches_json_temp_files() {
+        assert_eq!(temp_target(Path::new("data/auth/users.json.tmp")), Some(PathBuf::from("data/auth/users.json")));
+        assert_eq!(temp_target(Path::new("data/upload.tmp")), None);
+        assert_eq!(temp_target(Path::new("data/notes.txt.tmp")), None);
+        assert_eq!(temp_target(Path::new("data/.json.tmp")), None);
+        assert_eq!(temp_target(Path::new("data/users.json")), None);
+    }
+
+    #[test]
+    fn complete_temp_file_replaces_a_missing_target() {
+        let dir = test_dir("recover");
+        fs::write(dir.join("sessions.json.tmp"), "{\"a\": 1}").unwrap();
+
+        repair_temp_files(&dir);
+
+        assert_eq!(fs::read_to_string(dir.join("sessions.json")).unwrap(), "{\"a\": 1}");
+        assert!(!dir.join("sessions.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn partial_or_superseded_temp_files_are_removed() {
+        let dir = test_dir("remove");
+        // Truncated by the crash, the target was never replaced
+        fs::write(dir.join("users.json.tmp"), "{\"a\":").unwrap();
+        // Complete but the target exists, the rename didn't happen and the old file is kept
+        fs::write(dir.join("settings.json"), "{\"old\": true}").unwrap();
+        fs::write(dir.join("settings.json.tmp"), "{\"new\": true}").unwrap();
+
+        repair_temp_files(&dir);
+
+        assert!(!dir.join("users.json.tmp").exists());
+        assert!(!dir.join("users.json").exists());
+        assert!(!dir.join("settings.json.tmp").exists());
+        assert_eq!(fs::read_to_string(dir.join("settings.json")).unwrap(), "{\"old\": true}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_files_and_sub_directories_are_left_alone() {
+        let dir = test_dir("others");
+        fs::write(dir.join("upload.tmp"), "not json").unwrap();
+        fs::create_dir(dir.join("public")).unwrap();
+        fs::write(dir.join("public/foo.json.tmp"), "{}").unwrap();
+
+        repair_temp_files(&dir);
+
+        assert!(dir.join("upload.tmp").exists());
+        assert!(dir.join("public/foo.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_json_replaces_the_file_without_leaving_temp_files() {
+        let dir = test_dir("write");
+        let path = dir.join("config.json");
+        write_json(path.to_str().unwrap(), String::from("{\"v\": 1}")).unwrap();
+        write_json(path.to_str().unwrap(), String::from("{\"v\": 2}")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"v\": 2}");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
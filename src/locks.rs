@@ -0,0 +1,42 @@
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// A panic while a lock is held poisons it, and unwrapping it afterwards panics every later
+// request too. These take the data as the panicking thread left it and clear the poison.
+// Owners of state that a panic can leave half changed check is_poisoned first and reload it.
+// Not for the logger lock, the warning would be logged through the lock being recovered
+pub trait MutexRecover<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+pub trait RwLockRecover<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T> MutexRecover<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            log::warn!("Recovered a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+impl<T> RwLockRecover<T> for RwLock<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            log::warn!("Recovered a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            log::warn!("Recovered a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{stderr, Result, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, TryLockError};
+use chrono::{SecondsFormat, Utc};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+extern crate termion;
+use termion::{color};
+
+use crate::config::Config;
+
+// Server log, every line goes to stderr and once the config is loaded to data/logs too
+pub const LOG_PATH: &str = "data/logs/rpublish.log";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One readable line per entry, colored by level on a terminal
+    #[default]
+    Human,
+    // One json object per line, for log collectors
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "human" | "text" => Some(LogFormat::Human),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Log file that is renamed to `{path}.1` once it grows past max_size, the older
+// files are shifted up to `{path}.{keep}` and the last one is dropped
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, max_size: u64, keep: usize) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, max_size, keep, file, size })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        if self.max_size > 0 && self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotated_path(&self, number: usize) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(format!(".{}", number));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if self.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for number in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated_path(number), self.rotated_path(number + 1));
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+// Remove the terminal color sequences from a message, error strings may still carry them
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character == '\u{1b}' {
+            // CSI sequence, `ESC [ parameters final_byte`
+            if characters.next() == Some('[') {
+                for sequence_character in characters.by_ref() {
+                    if ('@'..='~').contains(&sequence_character) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            stripped.push(character);
+        }
+    }
+    stripped
+}
+
+// `rpublish::config` -> `config`, the crate name is the same on every line
+fn short_target(target: &str) -> &str {
+    target.strip_prefix("rpublish::").unwrap_or(target)
+}
+
+struct LoggerState {
+    format: LogFormat,
+    // Colors are only written to a terminal
+    color: bool,
+    file: Option<RotatingFile>
+}
+
+struct Logger {
+    state: Mutex<LoggerState>
+}
+
+static LOGGER: Logger = Logger {
+    state: Mutex::new(LoggerState { format: LogFormat::Human, color: false, file: None })
+};
+
+impl Logger {
+    fn line(format: LogFormat, color: bool, record: &Record, message: &str) -> String {
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let target = short_target(record.target());
+        match format {
+            LogFormat::Json => json!({
+                "time": time,
+                "level": record.level().as_str().to_lowercase(),
+                "target": target,
+                "message": message
+            }).to_string(),
+            LogFormat::Human if color => {
+                let level_color = match record.level() {
+                    Level::Error => format!("{}", color::Fg(color::Red)),
+                    Level::Warn => format!("{}", color::Fg(color::Yellow)),
+                    Level::Info => format!("{}", color::Fg(color::Cyan)),
+                    Level::Debug | Level::Trace => format!("{}", color::Fg(color::Green)),
+                };
+                format!(
+                    "{} {}{:<5}{} {}: {}",
+                    time, level_color, record.level(), color::Fg(color::Reset), target, message
+                )
+            },
+            LogFormat::Human => format!("{} {:<5} {}: {}", time, record.level(), target, message),
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = strip_ansi(&record.args().to_string());
+        // Keep logging after a panic in another thread while holding the lock
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let format = state.format;
+        let _ = writeln!(stderr(), "{}", Self::line(format, state.color, record, &message));
+        if let Some(file) = state.file.as_mut() {
+            let _ = file.write_line(&Self::line(format, false, record, &message));
+        }
+    }
+
+    fn flush(&self) {
+        let _ = stderr().flush();
+    }
+}
+
+fn env_level() -> Option<LevelFilter> {
+    std::env::var("RPUBLISH_LOG_LEVEL").ok().and_then(|level| level.parse().ok())
+}
+
+fn env_format() -> Option<LogFormat> {
+    std::env::var("RPUBLISH_LOG_FORMAT").ok().and_then(|format| LogFormat::parse(&format))
+}
+
+// Log to stderr from the start, the environment can already choose the level and format
+pub fn init() {
+    if let Ok(mut state) = LOGGER.state.lock() {
+        state.format = env_format().unwrap_or_default();
+        state.color = termion::is_tty(&stderr());
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(env_level().unwrap_or(LevelFilter::Info));
+    }
+    std::panic::set_hook(Box::new(log_panic));
+}
+
+// Panics go to the log file too, with the place they happened
+fn log_panic(info: &std::panic::PanicHookInfo) {
+    let message = info.payload().downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    let location = info.location().map(|location| location.to_string()).unwrap_or_default();
+    let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+    // A panic while logging holds the logger lock on this thread
+    if let Err(TryLockError::WouldBlock) = LOGGER.state.try_lock() {
+        eprintln!("Panic in thread {} at {}: {}", thread, location, message);
+        return;
+    }
+    log::error!("Panic in thread {} at {}: {}", thread, location, message);
+}
+
+// Format in use, for the other logs written next to this one
+pub fn format() -> LogFormat {
+    match LOGGER.state.lock() {
+        Ok(state) => state.format,
+        Err(poisoned) => poisoned.into_inner().format,
+    }
+}
+
+// Apply the config once loaded and start writing the log file, the environment still wins
+pub fn configure(config: &Config) {
+    let level = env_level()
+        .or_else(|| config.log_level.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    log::set_max_level(level);
+
+    let file = match RotatingFile::open(LOG_PATH, config.log_max_size_mb * 1024 * 1024, config.log_files) {
+        Ok(file) => Some(file),
+        Err(error) => {
+            log::error!("Failed to open {}: {}", LOG_PATH, error);
+            None
+        },
+    };
+    if let Ok(mut state) = LOGGER.state.lock() {
+        state.format = env_format().unwrap_or(config.log_format);
+        state.file = file;
+    }
+}
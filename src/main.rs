@@ -1,34 +1,129 @@
-use std::sync::{Mutex};
+use std::io::{Error, ErrorKind};
+use std::sync::{Mutex, RwLock};
 use actix_web::{App, HttpServer, web};
-extern crate termion;
-use termion::{color};
 
 mod helpers; // Initialization routines
+mod config; // Server configuration
+mod settings; // Public site settings
+mod logging; // Leveled server logs
+mod locks; // Locks that survive a panic
+mod error; // Errors answered by the request handlers
 mod rpublish; // RPublish system
 
 mod handlers;
 mod middleware;
+mod templates;
+mod themes;
+mod site; // Public site pages
+mod export; // Static site export
+mod admin; // Command line administration
+
+use rpublish::storage::StorageBackend;
+use rpublish::articles_manager::article;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    logging::init();
+
     // Check or create data directories
     match helpers::setup_system() {
         Ok(_) => {},
-        Err(setup_error) => println!("{}Initialization failed: {}", color::Fg(color::Red), setup_error)
+        Err(setup_error) => log::error!("Initialization failed: {}", setup_error)
+    }
+
+    let config = config::Config::load();
+    logging::configure(&config);
+
+    // `rpublish migrate [--from <backend>] --to <backend>` copies the data between storage backends
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // `rpublish export --out <dir>` renders the public site into static files, the other
+    // commands administrate users, articles and sessions, see `rpublish help`
+    match args.first().map(|command| command.as_str()) {
+        Some("migrate") => return migrate(&config, &args[1..]),
+        Some("export") => return export::export(&config, &args[1..]),
+        Some("user" | "article" | "session" | "cache" | "check") => {
+            // Scripts get the error message and a failed exit status
+            if let Err(error) = admin::run(&config, &args) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        Some("help" | "--help") => {
+            println!("{}", admin::USAGE);
+            return Ok(());
+        },
+        Some(command) => {
+            eprintln!("Unknown command {}\n\n{}", command, admin::USAGE);
+            std::process::exit(2);
+        },
+        None => {},
+    }
+
+    // One server per data directory, the lock is kept until the process ends
+    let mut data_lock = match helpers::lock_data_dir() {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            log::error!("Startup failed: the data directory is used by another rpublish process");
+            std::process::exit(1);
+        },
+        Err(error) => {
+            log::error!("Startup failed: can't lock the data directory: {}", error);
+            std::process::exit(1);
+        },
+    };
+    if let Err(error) = helpers::write_lock_pid(&mut data_lock) {
+        log::warn!("Failed to write the pid into the data directory lock: {}", error);
+    }
+    helpers::repair_interrupted_writes();
+
+    let storage = config.storage.open_recovered(&config.sqlite_path)?;
+    log::info!("Using {} storage", storage.name());
+    let history = open_history(&config);
+    let setup_wizard = config.setup_wizard || std::env::var("RPUBLISH_SETUP_WIZARD").map(|value| value == "1").unwrap_or(false);
+    let data = match rpublish::RPublishApp::new(storage, config.articles_cache_mb * 1024 * 1024, history, setup_wizard) {
+        Ok(app) => web::Data::new(app),
+        Err(error) => {
+            log::error!("Startup failed: {}", error);
+            std::process::exit(1);
+        },
+    };
+
+    if config.watch_articles {
+        if config.storage == StorageBackend::Filesystem {
+            if let Err(error) = rpublish::watcher::watch_articles(data.clone().into_inner()) {
+                log::error!("Failed to watch the articles directory: {}", error);
+            }
+        } else {
+            log::warn!("Articles watcher is only available with the filesystem storage");
+        }
     }
 
-    let data = web::Data::new(Mutex::new(
-        rpublish::RPublishApp::default()
-    ));
+    let access_log = middleware::access_log::AccessLog::open(&config);
 
-    println!("{}- Starting the server uwu", color::Fg(color::Yellow));
+    rpublish::stats::start_flush(data.clone().into_inner());
+    let app = data.clone();
+
+    let templates = web::Data::new(templates::Templates::from_env(&config.theme));
+    let config = web::Data::new(Mutex::new(config));
+    let site_settings = web::Data::new(RwLock::new(settings::SiteSettings::load()));
+
+    log::info!("Starting the server uwu");
     // Bind and start the server
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
         .app_data( data.clone() )
+        .app_data( templates.clone() )
+        .app_data( config.clone() )
+        .app_data( site_settings.clone() )
+        // Malformed bodies, queries and paths are answered like the handler errors
+        .app_data( web::JsonConfig::default().limit(article::MAX_UPDATE_SIZE).error_handler(error::json_error) )
+        .app_data( web::QueryConfig::default().error_handler(|error, _| error::AppError::BadRequest(error.to_string()).into()) )
+        .app_data( web::PathConfig::default().error_handler(|_, _| error::AppError::NotFound(String::from("Page not found")).into()) )
         .wrap( actix_web::middleware::NormalizePath::new(
             actix_web::middleware::normalize::TrailingSlash::Trim
         ))
+        .configure(handlers::health::configure)
         .service(web::scope("/auth").configure(handlers::auth::configure))
         .service(web::scope("/api").configure(handlers::api::configure))
         .service(
@@ -41,8 +136,65 @@ async fn main() -> std::io::Result<()> {
                 .show_files_listing()
                 .use_last_modified(true),
         )
+        .service(actix_files::Files::new("/uploads", "data/public").use_last_modified(true))
         .configure(handlers::public::configure)
-    }).bind("0.0.0.0:1337")?
+        .default_service(web::route().to(handlers::not_found))
+        .wrap( middleware::catch_panic::CatchPanic )
+        .wrap( middleware::error_pages::ErrorPages )
+        .wrap( middleware::metrics::RequestMetrics )
+        .wrap( access_log.clone() )
+    }).bind(config::BIND_ADDRESS)?
     .run()
-    .await
-}
\ No newline at end of file
+    .await;
+
+    // Keep the views counted since the last periodic save
+    app.stats.flush();
+    server
+}
+// The article history needs the article files, it is only kept with the filesystem storage
+fn open_history(config: &config::Config) -> Option<rpublish::history::GitHistory> {
+    if !config.git_history {
+        None
+    } else if config.storage != StorageBackend::Filesystem {
+        log::warn!("Article history is only available with the filesystem storage");
+        None
+    } else {
+        match rpublish::history::GitHistory::open(config.git_remote.to_owned()) {
+            Ok(history) => Some(history),
+            Err(error) => {
+                log::error!("Failed to open the article history repository: {}", error);
+                None
+            },
+        }
+    }
+}
+
+fn migrate(config: &config::Config, args: &[String]) -> std::io::Result<()> {
+    let mut origin = config.storage;
+    let mut target = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let backend = args.next().and_then(|name| StorageBackend::parse(name));
+        match (arg.as_str(), backend) {
+            ("--from", Some(backend)) => origin = backend,
+            ("--to", Some(backend)) => target = Some(backend),
+            _ => return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Usage: rpublish migrate [--from filesystem|sqlite] --to filesystem|sqlite"
+            )),
+        }
+    }
+    let target = target.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Missing --to <backend>"))?;
+    if origin == target {
+        return Err(Error::new(ErrorKind::InvalidInput, "Origin and target storage are the same"));
+    }
+
+    let origin = origin.open(&config.sqlite_path)?;
+    let target_storage = target.open(&config.sqlite_path)?;
+    rpublish::storage::migrate(origin.as_ref(), target_storage.as_ref())?;
+
+    let mut config = config.clone();
+    config.storage = target;
+    config.save();
+    Ok(())
+}
@@ -1 +1,5 @@
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod access_log;
+pub mod metrics;
+pub mod catch_panic;
+pub mod error_pages;
@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use actix_web::{HttpMessage, web};
+use actix_web::{dev::ServiceRequest, dev::Service, dev::Transform, dev::ServiceResponse, Error};
+use chrono::{SecondsFormat, Utc};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde_json::json;
+
+use crate::locks::MutexRecover;
+use crate::handlers::client_ip;
+use crate::config::Config;
+use crate::logging::{self, LogFormat, RotatingFile};
+use crate::rpublish;
+
+pub const ACCESS_LOG_PATH: &str = "data/logs/access.log";
+
+// Writes a line per request into data/logs/access.log with the method, path, status,
+// latency and user of the session. Query strings are left out, they can carry tokens
+#[derive(Clone)]
+pub struct AccessLog {
+    // None when the access log is disabled or can't be opened
+    file: Option<Arc<Mutex<RotatingFile>>>,
+    format: LogFormat
+}
+
+// Request details taken before the handler runs
+struct AccessEntry {
+    method: String,
+    path: String,
+    ip: String,
+    user: Option<String>
+}
+
+impl AccessLog {
+    // Same format and rotation as the server log
+    pub fn open(config: &Config) -> Self {
+        let file = if config.access_log {
+            match RotatingFile::open(ACCESS_LOG_PATH, config.log_max_size_mb * 1024 * 1024, config.log_files) {
+                Ok(file) => Some(Arc::new(Mutex::new(file))),
+                Err(error) => {
+                    log::error!("Failed to open {}: {}", ACCESS_LOG_PATH, error);
+                    None
+                },
+            }
+        } else {
+            None
+        };
+        Self {
+            file,
+            format: logging::format()
+        }
+    }
+
+    fn write(&self, file: &Mutex<RotatingFile>, entry: &AccessEntry, status: u16, latency_ms: f64) {
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let line = match self.format {
+            LogFormat::Json => json!({
+                "time": time,
+                "method": entry.method,
+                "path": entry.path,
+                "status": status,
+                "latency_ms": (latency_ms * 10.0).round() / 10.0,
+                "ip": entry.ip,
+                "user": entry.user
+            }).to_string(),
+            LogFormat::Human => format!(
+                "{} {} \"{} {}\" {} {:.1}ms {}",
+                time, entry.ip, entry.method, entry.path, status, latency_ms,
+                entry.user.as_deref().unwrap_or("-")
+            ),
+        };
+        let mut file = file.lock_or_recover();
+        if let Err(error) = file.write_line(&line) {
+            log::error!("Failed to write {}: {}", ACCESS_LOG_PATH, error);
+        }
+    }
+}
+
+impl<S, B> Transform<S> for AccessLog
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLogMiddleware { service, log: self.clone() })
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+    log: AccessLog
+}
+
+impl<S, B> Service for AccessLogMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let file = match &self.log.file {
+            Some(file) => file.clone(),
+            None => return Box::pin(self.service.call(req)),
+        };
+        let started = Instant::now();
+        // The session is read before the handler runs, a logout still shows who it was
+        let user = req.cookie("SESSID").and_then(|sessid_cookie| {
+            req.app_data::<web::Data<rpublish::RPublishApp>>()
+                .and_then(|app| app.identity_manager.sessions.get_user(sessid_cookie.value()))
+        });
+        let entry = AccessEntry {
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            // The same address as the audit log and the page statistics
+            ip: Some(client_ip(req.peer_addr(), &req.connection_info(), req.app_data::<web::Data<Mutex<Config>>>()))
+                .filter(|ip| !ip.is_empty())
+                .unwrap_or_else(|| String::from("-")),
+            user
+        };
+        let log = self.log.clone();
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            let response = response.await;
+            let status = match &response {
+                Ok(response) => response.status().as_u16(),
+                Err(error) => error.as_response_error().status_code().as_u16(),
+            };
+            log.write(&file, &entry, status, started.elapsed().as_secs_f64() * 1000.0);
+            response
+        })
+    }
+}
@@ -1,4 +1,3 @@
-use std::sync::Mutex;
 use std::task::{Context, Poll};
 
 use actix_web::{HttpMessage, HttpResponse, http, web};
@@ -6,6 +5,7 @@ use actix_web::{dev::ServiceRequest, dev::Service, dev::Transform, dev::ServiceR
 use futures::future::Either;
 use futures::{future::{ok, Ready}};
 
+use crate::error::{self, AppError};
 use crate::rpublish;
 
 // There are two steps in middleware processing.
@@ -57,26 +57,17 @@ where
     }
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
-        if let Some(sessid_cookie) = req.cookie("SESSID") {
-            let app = req.app_data::<web::Data<Mutex<rpublish::RPublishApp>>>()
-                .unwrap()
-                .lock()
-                .unwrap();
-            
-            let valid_session = app.identity_manager.sessions.validate(&sessid_cookie.value().to_string());
-            drop(app);
+        // Only reads the sessions in memory, requests are validated concurrently
+        let valid_session = req.cookie("SESSID")
+            .and_then(|sessid_cookie| req.app_data::<web::Data<rpublish::RPublishApp>>()
+                .map(|app| app.identity_manager.sessions.validate(sessid_cookie.value())))
+            .unwrap_or(false);
 
-            if valid_session
-            {
-                Either::Left(self.service.call(req))
-            } else {
-                Either::Right(ok(req.into_response(
-                    HttpResponse::Found()
-                        .header(http::header::LOCATION, "/auth/login")
-                        .finish()
-                        .into_body(),
-                )))
-            }
+        if valid_session {
+            Either::Left(self.service.call(req))
+        } else if error::is_api_path(req.path()) {
+            // Api clients can't follow the login redirect
+            Either::Right(ok(req.error_response(AppError::session_ended())))
         } else {
             Either::Right(ok(req.into_response(
                 HttpResponse::Found()
@@ -0,0 +1,71 @@
+use std::panic::AssertUnwindSafe;
+use std::task::{Context, Poll};
+
+use actix_web::{dev::ServiceRequest, dev::Service, dev::Transform, dev::ServiceResponse, Error};
+use futures::FutureExt;
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use crate::error::AppError;
+
+// Answers a request whose handler panicked with an error page, without it the connection
+// is dropped with no response. The panic itself is logged by the panic hook
+pub struct CatchPanic;
+
+impl<S, B> Transform<S> for CatchPanic
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CatchPanicMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CatchPanicMiddleware { service })
+    }
+}
+
+pub struct CatchPanicMiddleware<S> {
+    service: S,
+}
+
+// The panic itself is in the log, the client gets a server error
+fn panicked() -> Error {
+    AppError::Internal.into()
+}
+
+impl<S, B> Service for CatchPanicMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        // Handlers can panic while they are called or while their future runs. Locks held
+        // at that moment are recovered by the next request that takes them
+        let service = &mut self.service;
+        let response = match std::panic::catch_unwind(AssertUnwindSafe(|| service.call(req))) {
+            Ok(response) => response,
+            Err(_) => return Box::pin(async { Err(panicked()) }),
+        };
+        Box::pin(async move {
+            match AssertUnwindSafe(response).catch_unwind().await {
+                Ok(response) => response,
+                Err(_) => Err(panicked()),
+            }
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::task::{Context, Poll};
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode, web};
+use actix_web::{dev::ServiceRequest, dev::Service, dev::Transform, dev::ServiceResponse, Error};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use crate::error::{self, AppError};
+use crate::templates::Templates;
+
+// Replaces the json body of the handler errors with an error page outside of the api routes
+pub struct ErrorPages;
+
+impl<S, B> Transform<S> for ErrorPages
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ErrorPagesMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ErrorPagesMiddleware { service })
+    }
+}
+
+pub struct ErrorPagesMiddleware<S> {
+    service: S,
+}
+
+// Page of an error returned by a middleware, these come without the request to answer with
+struct RenderedPage {
+    status: StatusCode,
+    page: String,
+    // Debug output of the error, logged by actix for server errors
+    cause: String
+}
+
+impl fmt::Debug for RenderedPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.cause)
+    }
+}
+
+impl fmt::Display for RenderedPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.status)
+    }
+}
+
+impl ResponseError for RenderedPage {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .content_type("text/html; charset=utf-8")
+            .body(self.page.to_owned())
+    }
+}
+
+impl<S, B> Service for ErrorPagesMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if error::is_api_path(req.path()) {
+            return Box::pin(self.service.call(req));
+        }
+        let path = req.path().to_string();
+        let templates = req.app_data::<web::Data<Templates>>().map(|templates| templates.clone().into_inner());
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    let page = response.response().error()
+                        .and_then(|error| error.as_error::<AppError>())
+                        .map(|error| error.page(templates.as_deref(), &path));
+                    Ok(match page {
+                        Some(page) => response.into_response(page.into_body()),
+                        None => response,
+                    })
+                },
+                Err(error) => match error.as_error::<AppError>() {
+                    Some(app_error) => Err(RenderedPage {
+                        status: app_error.status_code(),
+                        page: app_error.page_html(templates.as_deref(), &path),
+                        cause: format!("{:?}", app_error)
+                    }.into()),
+                    None => Err(error),
+                },
+            }
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use actix_web::web;
+use actix_web::{dev::ServiceRequest, dev::Service, dev::Transform, dev::ServiceResponse, Error};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use crate::rpublish;
+
+// Counts the requests and their latency by matched route for /metrics, requests that
+// match no route are counted together as "unmatched"
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S> for RequestMetrics
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware { service })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let app = match req.app_data::<web::Data<rpublish::RPublishApp>>() {
+            Some(app) => app.clone(),
+            None => return Box::pin(self.service.call(req)),
+        };
+        let started = Instant::now();
+        let method = req.method().to_string();
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            let response = response.await;
+            // The route is only known once the request went through the router
+            let (route, status) = match &response {
+                Ok(response) => (response.request().match_pattern(), response.status().as_u16()),
+                Err(error) => (None, error.as_response_error().status_code().as_u16()),
+            };
+            let route = route.unwrap_or_else(|| String::from("unmatched"));
+            app.metrics.record_request(&method, &route, status, started.elapsed().as_secs_f64());
+            response
+        })
+    }
+}
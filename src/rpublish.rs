@@ -1,25 +1,51 @@
 //pub mod article;
 //pub mod article_metadata;
 pub mod articles_manager;
+pub mod audit;
 pub mod articles_cache;
 pub mod metadata_cache;
 pub mod identity_manager;
+pub mod editorjs;
+pub mod seo;
+pub mod stats;
+pub mod history;
+pub mod metrics;
+pub mod overview;
+pub mod storage;
+pub mod watcher;
 
 use articles_manager::{ArticlesManager};
+use audit::AuditLog;
+use history::GitHistory;
 use identity_manager::IdentityManager;
+use metrics::Metrics;
+use stats::PageStats;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use storage::Storage;
 
+// Shared by every worker without an outer lock, the managers synchronize their own state
 pub struct RPublishApp
 {
     pub identity_manager: IdentityManager,
-    pub articles_manager: ArticlesManager
+    pub articles_manager: ArticlesManager,
+    pub audit: AuditLog,
+    pub stats: PageStats,
+    pub metrics: Metrics,
+    pub started: DateTime<Utc>
 }
 
 impl RPublishApp {
-    pub fn default() -> RPublishApp {
-        RPublishApp {
-            identity_manager: IdentityManager::new(),
-            articles_manager: ArticlesManager::new()
-        }
+    // Fails when the users can't be loaded, the first admin can't be created or the audit log can't be opened
+    pub fn new(storage: Arc<dyn Storage>, articles_cache_budget: usize, history: Option<GitHistory>, setup_wizard: bool) -> std::io::Result<RPublishApp> {
+        Ok(RPublishApp {
+            identity_manager: IdentityManager::new(storage.clone(), setup_wizard)?,
+            articles_manager: ArticlesManager::new(storage, articles_cache_budget, history),
+            audit: AuditLog::open()?,
+            stats: PageStats::new(),
+            metrics: Metrics::default(),
+            started: Utc::now()
+        })
     }
 }
 
@@ -1,12 +1,212 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::locks::MutexRecover;
+use super::articles_manager::ArticleStatus;
+use super::articles_manager::article::Article;
+use super::editorjs::EditorDocument;
+use super::metadata_cache::ArticleMetadata;
+
+// Parsed articles kept in memory, published ones also keep their rendered html.
+// Entries are looked up with the update date of the article metadata so a changed
+// article is never served from the cache, the least recently used entries are
+// dropped when the memory budget is exceeded
 pub struct ArticlesCache{
+    lru: Mutex<LruState>,
+    // Max bytes used by the cached articles, 0 disables the cache
+    budget: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize
+}
+
+pub struct CachedArticle {
+    pub article: Article,
+    // Only published articles are shown on the public site
+    pub page: Option<ArticlePage>,
+    size: usize
+}
+
+// Rendered once with the metadata of the page head, a hit parses nothing
+pub struct ArticlePage {
+    pub html: String,
+    pub metadata: ArticleMetadata
+}
+
+#[derive(Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+    pub size: usize,
+    pub budget: usize
+}
+
+type CacheKey = (String, ArticleStatus);
+
+struct CacheEntry {
+    article: Arc<CachedArticle>,
+    last_used: u64
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    // Keys by last use, the first one is evicted first
+    usage: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    size: usize
+}
+
+impl LruState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.usage.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.usage.insert(tick, key.to_owned());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.usage.remove(&entry.last_used);
+            self.size -= entry.article.size;
+        }
+    }
 
+    fn evict_until(&mut self, budget: usize) {
+        while self.size > budget {
+            match self.usage.keys().next().copied() {
+                Some(last_used) => {
+                    if let Some(key) = self.usage.remove(&last_used) {
+                        self.remove(&key);
+                    }
+                },
+                None => break,
+            }
+        }
+    }
 }
+
 impl ArticlesCache {
-    pub fn new() -> ArticlesCache {
-        ArticlesCache{}
+    pub fn new(budget: usize) -> ArticlesCache {
+        ArticlesCache{
+            lru: Mutex::new(LruState::default()),
+            budget,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0)
+        }
+    }
+
+    // Cached article if it is still the version updated at update_date
+    pub fn get(&self, article_id: &str, status: ArticleStatus, update_date: &DateTime<Utc>) -> Option<Arc<CachedArticle>> {
+        let key = (article_id.to_string(), status);
+        let mut lru = self.lru();
+
+        let cached = match lru.entries.get(&key) {
+            Some(entry) if &entry.article.article.update_date == update_date => Some(entry.article.clone()),
+            Some(_) => {
+                lru.remove(&key);
+                None
+            },
+            None => None,
+        };
+
+        match cached {
+            Some(article) => {
+                lru.touch(&key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(article)
+            },
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            },
+        }
+    }
+
+    // Cache an article read from the storage, published articles are rendered once here
+    pub fn insert(&self, article_id: &str, status: ArticleStatus, article: Article) -> Arc<CachedArticle> {
+        let page = match status {
+            ArticleStatus::Published => {
+                let document = EditorDocument::parse_lossy(&article.data);
+                Some(ArticlePage {
+                    html: document.render_html(),
+                    metadata: ArticleMetadata::from_document(&article, &document)
+                })
+            },
+            ArticleStatus::Draft => None,
+        };
+        let size = Self::estimate_size(&article, &page);
+        let cached = Arc::new(CachedArticle { article, page, size });
+
+        // Articles bigger than the whole budget are returned without caching them
+        if size <= self.budget {
+            let key = (article_id.to_string(), status);
+            let mut lru = self.lru();
+            lru.remove(&key);
+            lru.size += size;
+            lru.entries.insert(key.to_owned(), CacheEntry { article: cached.clone(), last_used: 0 });
+            lru.touch(&key);
+            lru.evict_until(self.budget);
+        }
+        cached
     }
 
-    pub fn _is_cached(&mut self, _article_id: &str) -> bool {
-        false
+    // A panic can leave the entries and their size apart, the cache starts empty again
+    fn lru(&self) -> MutexGuard<'_, LruState> {
+        if self.lru.is_poisoned() {
+            log::warn!("Articles cache lock poisoned by a panic, emptying the cache");
+            let mut lru = self.lru.lock_or_recover();
+            *lru = LruState::default();
+            return lru;
+        }
+        self.lru.lock_or_recover()
     }
-}
\ No newline at end of file
+
+    pub fn invalidate(&self, article_id: &str, status: ArticleStatus) {
+        self.lru().remove(&(article_id.to_string(), status));
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.lru.is_poisoned()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let lru = self.lru();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: lru.entries.len(),
+            size: lru.size,
+            budget: self.budget
+        }
+    }
+
+    // Bytes held by the strings of the entry, enough to keep the cache near its budget
+    fn estimate_size(article: &Article, page: &Option<ArticlePage>) -> usize {
+        let optional_len = |value: &Option<String>| value.as_ref().map_or(0, |value| value.len());
+        std::mem::size_of::<CachedArticle>()
+            + article.title.len()
+            + article.author.len()
+            + article.data.len()
+            + article.tags.iter().map(|tag| tag.len()).sum::<usize>()
+            + optional_len(&article.excerpt)
+            + optional_len(&article.cover_image)
+            + optional_len(&article.seo_title)
+            + optional_len(&article.seo_description)
+            + optional_len(&article.social_image)
+            + page.as_ref().map_or(0, |page| {
+                page.html.len()
+                    + page.metadata.title.len()
+                    + page.metadata.author.len()
+                    + page.metadata.tags.iter().map(|tag| tag.len()).sum::<usize>()
+                    + page.metadata.excerpt.len()
+                    + optional_len(&page.metadata.cover_image)
+            })
+    }
+}
@@ -1,233 +1,181 @@
 pub mod article;
+pub mod listing;
 
 use std::collections::HashMap;
 use std::io::ErrorKind;
-use std::{fmt, fs};
-use std::path::Path;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
-extern crate termion;
-use termion::{color};
-
+use crate::locks::{MutexRecover, RwLockRecover};
 use crate::rpublish::metadata_cache::MetadataCache;
-use crate::rpublish::articles_cache::ArticlesCache;
+use crate::rpublish::articles_cache::{ArticlesCache, CacheStats, CachedArticle};
 
-use self::article::Article;
-use crate::helpers::{write_json, move_file};
+use self::article::{Article, ArticleUpdate};
+use self::listing::{ArticleFilter, ArticleListing, ListingCursor, ListingSort, ListingStatus, SortField, SortOrder};
+use crate::rpublish::history::GitHistory;
+use crate::rpublish::storage::Storage;
 
 use super::metadata_cache::ArticleMetadata;
 
+// Articles are read concurrently, writes to the same article are serialized while
+// different articles are written in parallel. Storage calls block, run them off the async threads
 pub struct ArticlesManager{
+    storage: Arc<dyn Storage>,
+
+    // Lists and metadata, locked only while they are read or changed in memory
+    index: RwLock<ArticlesIndex>,
+    // One lock per article id, held during the whole write
+    article_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+
+    articles_cache: ArticlesCache,
+    // Commits every change made from the dashboard when enabled
+    history: Option<GitHistory>
+}
+
+pub struct ArticlesIndex {
     // Metadata
     published_metadata_cache: MetadataCache,
     draft_metadata_cache: MetadataCache,
 
-    _articles_cache: ArticlesCache,
     published_list: Vec<String>,
     draft_list: Vec<String>
 }
+
 impl ArticlesManager {
-    pub fn new() -> ArticlesManager {
-        let mut manager = Self::load_articles();
-        manager.build_draft_metadata();
-        manager.build_published_metadata();
+    pub fn new(storage: Arc<dyn Storage>, cache_budget: usize, history: Option<GitHistory>) -> ArticlesManager {
+        let manager = Self::load_articles(storage, cache_budget, history);
+        manager.build_metadata(ArticleStatus::Draft, false);
+        manager.build_metadata(ArticleStatus::Published, false);
         manager
     }
 
-    fn load_articles() -> ArticlesManager {
-        let published_ids = Self::read_ids(Path::new("data/articles/published"));
-        let draft_ids = Self::read_ids(Path::new("data/articles/draft"));
+    // Rebuild every metadata entry from the articles, for entries edited or corrupted by hand
+    pub fn rebuild_metadata (&self) {
+        self.build_metadata(ArticleStatus::Draft, true);
+        self.build_metadata(ArticleStatus::Published, true);
+    }
 
+    fn load_articles(storage: Arc<dyn Storage>, cache_budget: usize, history: Option<GitHistory>) -> ArticlesManager {
         Self {
-            published_metadata_cache: MetadataCache::new("published"),
-            draft_metadata_cache: MetadataCache::new("draft"),
-            _articles_cache: ArticlesCache::new(),
-            published_list: published_ids,
-            draft_list: draft_ids
-        }
-    }
-
-    fn read_ids(path: &Path) -> Vec<String> {
-        match fs::read_dir(path) {
-            Ok(published_files) => {
-                let mut ids: Vec<String> = Vec::new();
-                for file in published_files {
-                    let file_path = file.unwrap().path();
-                    if let Some(extension) = file_path.extension() {
-                        if extension == "json" {
-                            ids.push(String::from(file_path.file_stem().unwrap().to_str().unwrap()));
-                        }
-                    }
-                }
-                ids
-            },
-            Err(_) => {
-                println!("{}Failed read files from{}", color::Fg(color::Red), path.to_str().unwrap());
-                Vec::new()
-            },
+            index: RwLock::new(Self::load_index(storage.as_ref())),
+            article_locks: Mutex::new(HashMap::new()),
+            articles_cache: ArticlesCache::new(cache_budget),
+            history,
+            storage
         }
     }
 
-    fn build_draft_metadata (&mut self) {
-        for article_id in &self.draft_list {
-            if !self.draft_metadata_cache.is_cached(article_id) {
-                match self.read_from(article_id, ArticleStatus::Draft) {
-                    Some(article) => {
-                        self.draft_metadata_cache.set_metadata(article_id, &article);
-                    },
-                    None => {
-                        println!("Failed to read article when building draft metadata");
-                    },
-                }
-            }
+    fn load_index(storage: &dyn Storage) -> ArticlesIndex {
+        ArticlesIndex {
+            published_metadata_cache: MetadataCache::new("published", storage),
+            draft_metadata_cache: MetadataCache::new("draft", storage),
+            published_list: storage.list_articles(ArticleStatus::Published),
+            draft_list: storage.list_articles(ArticleStatus::Draft)
         }
     }
 
-    fn build_published_metadata (&mut self) {
-        for article_id in &self.published_list {
-            if !self.published_metadata_cache.is_cached(article_id) {
-                match self.read_from(article_id, ArticleStatus::Published) {
-                    Some(article) => {
-                        self.published_metadata_cache.set_metadata(article_id, &article);
-                    },
-                    None => {
-                        println!("Failed to read article when building published metadata");
-                    },
-                }
-            }
-        }
+    // A panic while the index was changed can leave the lists and the metadata apart,
+    // they are read again from the storage
+    fn reload_index(&self) {
+        log::error!("Articles index lock poisoned by a panic, reloading it from the storage");
+        *self.index.write_or_recover() = Self::load_index(self.storage.as_ref());
+        self.build_metadata(ArticleStatus::Draft, false);
+        self.build_metadata(ArticleStatus::Published, false);
     }
 
-    fn rebuild_metadata (&mut self, article_id: &str, origin: ArticleStatus) {
-        match self.read_from(article_id, origin) {
-            Some(article) => {
-                let metadata_cache: &mut MetadataCache;
-
-                if let ArticleStatus::Draft = origin {
-                    metadata_cache = &mut self.draft_metadata_cache;
-                } else {
-                    metadata_cache = &mut self.published_metadata_cache;
-                }
-                
-                metadata_cache.set_metadata(article_id, &article);
-            },
-            None => {
-                println!("Failed to read article to rebuild metadata")
-            },
+    // Startup consistency check, entries of missing articles are removed and entries missing
+    // or older than their article are rebuilt, or every entry with rebuild_all
+    fn build_metadata (&self, status: ArticleStatus, rebuild_all: bool) {
+        let index = self.index();
+        let metadata_cache = index.metadata_cache(status);
+        let listed: Vec<(String, Option<DateTime<Utc>>)> = index.list(status).iter()
+            .map(|article_id| (
+                article_id.to_owned(),
+                metadata_cache.get_metadata(article_id).map(|metadata| metadata.update_date.to_owned())
+            ))
+            .collect();
+        let stale: Vec<String> = metadata_cache.articles.keys()
+            .filter(|article_id| !index.is_listed(article_id, status))
+            .cloned()
+            .collect();
+        drop(index);
+
+        for article_id in &stale {
+            self.remove_metadata(article_id, status);
         }
-    }
-
-    pub fn list_draft_articles (&mut self, start_index: usize, count: usize) -> (HashMap<String, &ArticleMetadata>, usize) {
-        // Create a list for the returned refs
-        let mut articles_metadata: HashMap<String, &ArticleMetadata> = HashMap::new();
-
-        // Total of articles count
-        let total = self.draft_list.len();
-        
-        // Normalize count
-        let count = if count <= total {count} else {total};
 
-        // Check if the start index is in bounds
-        if start_index < total {
-            let list_slice: &[String];
-
-            // Get the correct slice
-            if start_index < total - count {
-                list_slice = &self.draft_list[start_index..start_index + count];
-            } else {
-                list_slice = &self.draft_list[start_index..];
+        let mut rebuilt = 0;
+        for (article_id, cached_date) in listed {
+            match self.storage.read_article(&article_id, status) {
+                Some(article) => {
+                    if rebuild_all || cached_date != Some(article.update_date) {
+                        self.set_metadata(&article_id, &article, status);
+                        rebuilt += 1;
+                    }
+                },
+                None => {
+                    log::error!("Failed to read article when building {} metadata", status.dir_name());
+                },
             }
+        }
 
-            for article_id in list_slice {
-                match self.draft_metadata_cache.get_metadata(article_id) {
-                    Some(metadata) => {
-                        articles_metadata.insert(article_id.to_string(),metadata);
-                    },
-                    None => {
-                        println!("Failed to get metadata of article when listing draft articles")
-                    },
-                }
-            }
+        if !stale.is_empty() || rebuilt > 0 {
+            log::info!("{} metadata: {} stale entries removed, {} rebuilt", status.dir_name(), stale.len(), rebuilt);
         }
-        // Return metadata list
-        (articles_metadata, total)
     }
 
-    pub fn list_published_articles (&mut self, start_index: usize, count: usize) -> (HashMap<String, &ArticleMetadata>, usize) {
-        // Create a list for the returned refs
-        let mut articles_metadata: HashMap<String, &ArticleMetadata> = HashMap::new();
-
-        // Total of articles count
-        let total = self.published_list.len();
-
-        let count = if count <= total {count} else {total};
+    // Read access to the article lists and metadata, keep it short as writers wait for it
+    pub fn index(&self) -> RwLockReadGuard<'_, ArticlesIndex> {
+        if self.index.is_poisoned() {
+            self.reload_index();
+        }
+        self.index.read_or_recover()
+    }
 
-        // Check if the start index is in bounds
-        if start_index < total {
-            let list_slice: &[String];
+    fn index_mut(&self) -> RwLockWriteGuard<'_, ArticlesIndex> {
+        if self.index.is_poisoned() {
+            self.reload_index();
+        }
+        self.index.write_or_recover()
+    }
 
-            // Get the correct slice
-            if start_index < total - count {
-                list_slice = &self.published_list[start_index..start_index + count];
-            } else {
-                list_slice = &self.published_list[start_index..];
-            }
+    // Serialize the writes of an article, the returned lock is held until the write is done
+    fn article_lock(&self, article_id: &str) -> Arc<Mutex<()>> {
+        self.article_locks.lock_or_recover()
+            .entry(article_id.to_string())
+            .or_default()
+            .clone()
+    }
 
-            for article_id in list_slice {
-                match self.published_metadata_cache.get_metadata(article_id) {
-                    Some(metadata) => {
-                        articles_metadata.insert(article_id.to_string(),metadata);
-                    },
-                    None => {
-                        println!("Failed to get metadata of article when listing published articles")
-                    },
-                }
-            }
+    // Derive the metadata outside the index lock, only the insert holds it
+    fn set_metadata (&self, article_id: &str, article: &Article, status: ArticleStatus) {
+        let metadata = ArticleMetadata::from_article(article);
+        if let Err(error) = self.storage.save_metadata(status.dir_name(), article_id, &metadata) {
+            log::error!("Failed to save metadata of {}: {}", article_id, error);
         }
-        // Return metadata list
-        (articles_metadata, total)
+        self.index_mut().metadata_cache_mut(status).set_metadata(article_id, metadata);
     }
 
-    fn read_article (file_path: &str) -> Option<Article> {
-        match fs::read_to_string(file_path) {
-            Ok(article_string) => {
-                match serde_json::from_str::<Article>(article_string.as_str()) {
-                    Ok(article) => {
-                        Some(article)
-                    },
-                    Err(_) => {
-                        None
-                    }
-                }
-            },
-            Err(_) => None,
-        }
-    }
-
-    fn save_article(&self, article_id: &str, article: &Article, status: ArticleStatus) {
-        match serde_json::to_string(article) {
-            Ok(json) => {
-                match status {
-                    ArticleStatus::Draft => {
-                        match write_json(format!("data/articles/draft/{}.json", article_id).as_str(), json) {
-                            Ok(_) => println!("{}Article draft saved{}", color::Fg(color::Cyan), color::Fg(color::Reset)),
-                            Err(_) => println!("{}Failed to save draft article file{}", color::Fg(color::Red), color::Fg(color::Reset)),
-                        }
-                    },
-                    ArticleStatus::Published => {
-                        match write_json(format!("data/articles/published/{}.json", article_id).as_str(), json) {
-                            Ok(_) => println!("{}Article saved to published{}", color::Fg(color::Cyan), color::Fg(color::Reset)),
-                            Err(_) => println!("{}Failed to save article to publishs{}", color::Fg(color::Red), color::Fg(color::Reset)),
-                        }
-                    }
-                }
-            },
-            Err(_) => println!("{}Failed to serialize users file", color::Fg(color::Red))
+    fn remove_metadata (&self, article_id: &str, status: ArticleStatus) {
+        self.index_mut().metadata_cache_mut(status).remove_metadata(article_id);
+        if let Err(error) = self.storage.remove_metadata(status.dir_name(), article_id) {
+            log::error!("Failed to remove metadata of {}: {}", article_id, error);
         }
     }
 
-    pub fn create(&mut self, article_id: &str, author: &str) {
+    fn save_article(&self, article_id: &str, article: &Article, status: ArticleStatus) -> std::io::Result<()> {
+        self.articles_cache.invalidate(article_id, status);
+        self.storage.write_article(article_id, article, status)?;
+        log::info!("Article saved to {}", status.dir_name());
+        Ok(())
+    }
+
+    pub fn create(&self, article_id: &str, author: &str) -> Result<(), ArticleError> {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
         let new_article = Article {
             title: String::from("Draft Article"),
             author: String::from(author),
@@ -235,249 +183,515 @@ impl ArticlesManager {
             data: String::new(),
             created_date: chrono::offset::Utc::now(),
             update_date: chrono::offset::Utc::now(),
+            excerpt: None,
+            cover_image: None,
+            seo_title: None,
+            seo_description: None,
+            social_image: None,
+            noindex: false,
         };
 
-        self.draft_metadata_cache.set_metadata(article_id, &new_article);
-        self.save_article(article_id, &new_article, ArticleStatus::Draft);
-        self.draft_list.push(article_id.to_string());
+        self.save_article(article_id, &new_article, ArticleStatus::Draft)?;
+        self.set_metadata(article_id, &new_article, ArticleStatus::Draft);
+        self.index_mut().add_to_list(article_id, ArticleStatus::Draft);
+        self.record_history(article_id, author, &format!("Create article {}", article_id));
+        Ok(())
     }
 
     pub fn read_latest (&self, article_id: &str) -> Option<(Article, ArticleStatus, bool, Option<DateTime<Utc>>)> {
-        if self.draft_list.contains(&article_id.to_string()) {
-            match Self::read_article(format!("data/articles/draft/{}.json", article_id).as_str()) {
-                Some(article) => {
-                    let is_published = self.published_list.contains(&article_id.to_string());
-                    let published_date: Option<DateTime<Utc>>;
-
-                    if is_published {
-                        match self.published_metadata_cache.get_metadata(article_id) {
-                            Some(metadata) => published_date = Some(metadata.update_date.to_owned()),
-                            None => published_date = None,
-                        }
-                    } else {
-                        published_date = None;
-                    }
+        let (is_draft, is_published, published_date) = {
+            let index = self.index();
+            (
+                index.is_listed(article_id, ArticleStatus::Draft),
+                index.is_listed(article_id, ArticleStatus::Published),
+                index.published_metadata_cache.get_metadata(article_id).map(|metadata| metadata.update_date.to_owned())
+            )
+        };
 
-                    Some((
-                        article, 
-                        ArticleStatus::Draft, 
-                        is_published,
-                        published_date
-                    ))
-                },
-                None => None,
-            }
-        } else if self.published_list.contains(&article_id.to_string()) {
-            match Self::read_article(format!("data/articles/published/{}.json", article_id).as_str()) {
-                Some(article) => {
-                    let published_date = Some(article.update_date.to_owned());
-                    Some((
-                        article, 
-                        ArticleStatus::Published, 
-                        self.published_list.contains(&article_id.to_string()),
-                        published_date
-                    ))
-                },
-                None => None,
-            }
+        if is_draft {
+            self.read_from(article_id, ArticleStatus::Draft).map(|article| {
+                (
+                    article,
+                    ArticleStatus::Draft,
+                    is_published,
+                    if is_published { published_date } else { None }
+                )
+            })
+        } else if is_published {
+            self.read_from(article_id, ArticleStatus::Published).map(|article| {
+                let published_date = Some(article.update_date.to_owned());
+                (
+                    article,
+                    ArticleStatus::Published,
+                    true,
+                    published_date
+                )
+            })
+        } else {
+            None
+        }
+    }
+
+    // Read the public version of an article with its rendered page
+    pub fn read_published (&self, article_id: &str) -> Option<Arc<CachedArticle>> {
+        if self.index().is_listed(article_id, ArticleStatus::Published) {
+            self.read_cached(article_id, ArticleStatus::Published)
         } else {
             None
         }
     }
 
     pub fn read_from (&self, article_id: &str, status: ArticleStatus) -> Option<Article>{
-        match status {
-            ArticleStatus::Draft => {
-                Self::read_article(format!("data/articles/draft/{}.json", article_id).as_str())
+        self.read_cached(article_id, status).map(|cached| cached.article.clone())
+    }
+
+    // Cache entries are valid while their update date matches the listed metadata
+    fn read_cached (&self, article_id: &str, status: ArticleStatus) -> Option<Arc<CachedArticle>> {
+        let update_date = self.index().metadata_cache(status).get_metadata(article_id)
+            .map(|metadata| metadata.update_date.to_owned());
+        if let Some(cached) = update_date.and_then(|update_date| self.articles_cache.get(article_id, status, &update_date)) {
+            return Some(cached);
+        }
+        let article = self.storage.read_article(article_id, status)?;
+        Some(self.articles_cache.insert(article_id, status, article))
+    }
+
+    pub fn cache_stats (&self) -> CacheStats {
+        self.articles_cache.stats()
+    }
+
+    // A thread panicked while changing the index, the article locks or the cache
+    pub fn is_poisoned (&self) -> bool {
+        self.index.is_poisoned() || self.article_locks.is_poisoned() || self.articles_cache.is_poisoned()
+    }
+
+    // Reload the index and empty the cache when a panic poisoned them
+    pub fn recover (&self) {
+        drop(self.index());
+        drop(self.article_locks.lock_or_recover());
+        self.articles_cache.stats();
+    }
+
+    // Bring the lists and metadata in line with an article changed outside the server,
+    // it is listed while the storage can read it
+    pub fn reload_article(&self, article_id: &str, status: ArticleStatus) {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        self.articles_cache.invalidate(article_id, status);
+        let (is_listed, cached_date) = {
+            let index = self.index();
+            (
+                index.is_listed(article_id, status),
+                index.metadata_cache(status).get_metadata(article_id).map(|metadata| metadata.update_date.to_owned())
+            )
+        };
+
+        match self.storage.read_article(article_id, status) {
+            Some(article) => {
+                if cached_date != Some(article.update_date) {
+                    self.set_metadata(article_id, &article, status);
+                }
+                if !is_listed {
+                    self.index_mut().add_to_list(article_id, status);
+                    log::info!("Picked up {} article {}", status.dir_name(), article_id);
+                } else if cached_date != Some(article.update_date) {
+                    log::info!("Reloaded changed {} article {}", status.dir_name(), article_id);
+                }
+            },
+            None => {
+                if is_listed {
+                    self.index_mut().remove_from_list(article_id, status);
+                    self.remove_metadata(article_id, status);
+                    log::info!("Removed {} article {} deleted from storage", status.dir_name(), article_id);
+                }
             },
-            ArticleStatus::Published => {
-                Self::read_article(format!("data/articles/published/{}.json", article_id).as_str())
-            }
         }
     }
 
-    pub fn update(&mut self, article_id: &str, title: &str, data: &str) -> Result<(), ArticleError> {
-        match self.read_latest(&article_id) {
+    pub fn update(&self, article_id: &str, changes: &ArticleUpdate, editor: &str) -> Result<(), ArticleError> {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        match self.read_latest(article_id) {
             Some(mut article) => {
-                article.0.title = title.to_string();
-                article.0.data = data.to_string();
+                article.0.apply(changes);
                 article.0.update_date = chrono::offset::Utc::now();
-                self.draft_metadata_cache.set_metadata(article_id, &article.0);
-                self.save_article(article_id, &article.0, ArticleStatus::Draft);
-
-                let article_id_string = article_id.to_string();
-                if !self.draft_list.contains(&article_id_string) {
-                    self.draft_list.push(article_id_string);
-                }
+                self.save_article(article_id, &article.0, ArticleStatus::Draft)?;
+                self.set_metadata(article_id, &article.0, ArticleStatus::Draft);
+                self.index_mut().add_to_list(article_id, ArticleStatus::Draft);
+                self.record_history(article_id, editor, &format!("Update article {}: {}", article_id, article.0.title));
                 Ok(())
             },
-            None => {
-                Err(ArticleError{
-                    kind: ArticleErrorKind::ArticleNotFound
-                })
+            None => Err(ArticleError::new(ArticleErrorKind::ArticleNotFound)),
+        }
+    }
+
+    pub fn discard_changes(&self, article_id: &str, editor: &str) -> Result<(), ArticleError> {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        let (is_draft, is_published) = self.listed_as(article_id);
+        match (is_draft, is_published) {
+            (false, false) => Err(ArticleError::new(ArticleErrorKind::ArticleNotFound)),
+            (true, false) => Err(ArticleError::new(ArticleErrorKind::ArticleNotPublished)),
+            (false, true) => Err(ArticleError::new(ArticleErrorKind::NoDraft)),
+            (true, true) => {
+                self.delete_article(article_id, ArticleStatus::Draft)?;
+                self.record_history(article_id, editor, &format!("Discard changes of article {}", article_id));
+                Ok(())
             },
         }
     }
 
-    pub fn discard_changes(&mut self, article_id: &str) -> Result<(), std::io::Error> {
-        let is_published = self.published_list.contains(&article_id.to_string());
-        if is_published {
-            match self.delete_article(article_id, ArticleStatus::Draft) {
-                Ok(_) => {
-                    Ok(())
-                },
-                Err(error) => Err(error),
+    // Both versions are moved to the trash, they can be restored until deleted again
+    pub fn delete(&self, article_id: &str, editor: &str) -> Result<(), ArticleError> {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        if self.listed_as(article_id) == (false, false) {
+            return Err(ArticleError::new(ArticleErrorKind::ArticleNotFound));
+        }
+
+        // Unlisted once in the trash, a failed move leaves the version listed where it was
+        for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+            match self.storage.trash_article(article_id, status) {
+                Ok(_) => {},
+                Err(error) if error.kind() == ErrorKind::NotFound => {},
+                Err(error) => return Err(error.into()),
             }
-        } else {
-            Err(std::io::Error::new(ErrorKind::NotFound, "Article is not published"))
+            self.articles_cache.invalidate(article_id, status);
+            self.index_mut().remove_from_list(article_id, status);
+            self.remove_metadata(article_id, status);
         }
+        self.record_history(article_id, editor, &format!("Delete article {}", article_id));
+        Ok(())
     }
 
-    pub fn delete(&mut self, article_id: &str) -> Result<(), std::io::Error> {
-        if let Err(error) = self.delete_article(article_id, ArticleStatus::Draft) {
-            if error.kind() != ErrorKind::NotFound {
-                return Err(error)
+    // Bring back the trashed versions of a deleted article
+    pub fn restore(&self, article_id: &str, editor: &str) -> Result<(), ArticleError> {
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        let mut restored = false;
+        for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+            match self.storage.restore_article(article_id, status) {
+                Ok(_) => {
+                    if let Some(article) = self.storage.read_article(article_id, status) {
+                        self.set_metadata(article_id, &article, status);
+                    }
+                    self.index_mut().add_to_list(article_id, status);
+                    restored = true;
+                },
+                Err(error) if error.kind() == ErrorKind::NotFound => {},
+                Err(error) => return Err(error.into()),
             }
         }
-        if let Err(error) = self.delete_article(article_id, ArticleStatus::Published) {
-            if error.kind() != ErrorKind::NotFound {
-                return Err(error)
-            }
+        if !restored {
+            return Err(ArticleError::new(ArticleErrorKind::ArticleNotFound));
         }
+        self.record_history(article_id, editor, &format!("Restore article {}", article_id));
         Ok(())
     }
 
-    pub fn publish(&mut self, article_id: &str) -> Result<(), std::io::Error>{
-        self.move_article(article_id, ArticleStatus::Draft, ArticleStatus::Published)
+    // Ids of the deleted articles that can be restored
+    pub fn list_trashed(&self) -> Vec<String> {
+        let mut trashed = self.storage.list_trashed(ArticleStatus::Draft);
+        for article_id in self.storage.list_trashed(ArticleStatus::Published) {
+            if !trashed.contains(&article_id) {
+                trashed.push(article_id);
+            }
+        }
+        trashed.sort();
+        trashed
     }
 
-    pub fn unpublish(&mut self, article_id: &str) -> Result<(), std::io::Error>{
+    pub fn publish(&self, article_id: &str, editor: &str) -> Result<(), ArticleError>{
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
 
-        if self.draft_list.contains(&article_id.to_string()) {
+        match self.listed_as(article_id) {
+            (false, false) => return Err(ArticleError::new(ArticleErrorKind::ArticleNotFound)),
+            // Published without changes, there is nothing new to publish
+            (false, true) => return Err(ArticleError::new(ArticleErrorKind::NoDraft)),
+            _ => {},
+        }
+        self.move_article(article_id, ArticleStatus::Draft, ArticleStatus::Published)?;
+        self.record_history(article_id, editor, &format!("Publish article {}", article_id));
+        Ok(())
+    }
+
+    pub fn unpublish(&self, article_id: &str, editor: &str) -> Result<(), ArticleError>{
+        let article_lock = self.article_lock(article_id);
+        let _guard = article_lock.lock_or_recover();
+
+        let (is_draft, is_published) = self.listed_as(article_id);
+        if is_draft && !is_published {
+            return Err(ArticleError::new(ArticleErrorKind::ArticleNotPublished));
+        } else if is_draft {
             // Just remove the published version and keep the draft
-            self.delete_article(article_id, ArticleStatus::Published)
-        } else if self.published_list.contains(&article_id.to_string()) {
+            self.delete_article(article_id, ArticleStatus::Published)?;
+        } else if is_published {
             // We need to move the published article to the draft section
-            self.move_article(article_id, ArticleStatus::Published, ArticleStatus::Draft)
+            self.move_article(article_id, ArticleStatus::Published, ArticleStatus::Draft)?;
         } else {
-            Err(std::io::Error::new(ErrorKind::NotFound, "Article not found"))
+            return Err(ArticleError::new(ArticleErrorKind::ArticleNotFound));
         }
+        self.record_history(article_id, editor, &format!("Unpublish article {}", article_id));
+        Ok(())
     }
 
-    fn delete_article (&mut self, article_id: &str, origin: ArticleStatus) -> Result<(), std::io::Error> {
-        let origin_str: &str;
-        let origin_list: &mut Vec<String>;
-        let metadata_cache: &mut MetadataCache;
+    // Whether the article has a draft and a published version
+    fn listed_as(&self, article_id: &str) -> (bool, bool) {
+        let index = self.index();
+        (index.is_listed(article_id, ArticleStatus::Draft), index.is_listed(article_id, ArticleStatus::Published))
+    }
 
-        if let ArticleStatus::Draft = origin {
-            origin_str = "draft";
-            origin_list = &mut self.draft_list;
-            metadata_cache = &mut self.draft_metadata_cache;
-        } else {
-            origin_str = "published";
-            origin_list = &mut self.published_list;
-            metadata_cache = &mut self.published_metadata_cache;
-        }
-        
-        // Remove the id from the published list
-        let article_id_string = article_id.to_string();
-        if let Some(id_index) = origin_list.iter().position(|x| x == &article_id_string) {
-            origin_list.remove(id_index);
-        }
-
-        metadata_cache.remove_metadata(article_id);
-
-        // Remove published file
-        match fs::remove_file(format!("data/articles/{}/{}.json", origin_str, article_id)) {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error),
-        }
-    }
-
-    fn move_article (&mut self, article_id: &str, origin: ArticleStatus, target: ArticleStatus) -> Result<(), std::io::Error> {
-        let origin_str: &str;
-        let target_str: &str;
-        let origin_list: &mut Vec<String>;
-        let target_list: &mut Vec<String>;
-        let metadata_cache: &mut MetadataCache;
-        
-        if let ArticleStatus::Draft = origin {
-            origin_str = "draft";
-            target_str = "published";
-            origin_list = &mut self.draft_list;
-            target_list = &mut self.published_list;
-            metadata_cache = &mut self.draft_metadata_cache;
-        } else {
-            origin_str = "published";
-            target_str = "draft";
-            origin_list = &mut self.published_list;
-            target_list = &mut self.draft_list;
-            metadata_cache = &mut self.published_metadata_cache;
-        }
-
-        match move_file(
-            format!("data/articles/{}/{}.json", origin_str, article_id).as_str(),
-            format!("data/articles/{}/{}.json", target_str, article_id).as_str()
-        ) {
-            Ok(_) => {
-                let article_id_string = article_id.to_string();
-
-                match origin_list.iter().position(|x| x == &article_id_string) {
-                    Some(id_index) => {
-                        origin_list.remove(id_index);
-                    },
-                    None => {
-                        println!("Failed to find article_id index to remove it from the origin list");
-                    },
-                }
+    // A failed commit doesn't undo the change, it is included in the next commit of the article
+    fn record_history(&self, article_id: &str, editor: &str, message: &str) {
+        if let Some(history) = &self.history {
+            if let Err(error) = history.commit(article_id, editor, message) {
+                log::error!("Failed to commit history of article {}: {}", article_id, error);
+            }
+        }
+    }
 
-                metadata_cache.remove_metadata(article_id);
+    // None when the history is disabled
+    pub fn history(&self) -> Option<&GitHistory> {
+        self.history.as_ref()
+    }
 
-                if !target_list.contains(&article_id_string) {
-                    target_list.push(article_id_string);
-                }
+    // Callers hold the article lock. The stored article is removed first so a failure
+    // leaves it listed
+    fn delete_article (&self, article_id: &str, origin: ArticleStatus) -> Result<(), std::io::Error> {
+        self.articles_cache.invalidate(article_id, origin);
+        self.storage.delete_article(article_id, origin)?;
 
-                match self.read_from (article_id, target) {
-                    Some(mut article) => {
-                        article.update_date = chrono::offset::Utc::now();
-                        self.save_article(article_id, &article, target);
-                    },
-                    None => {
-                        println!("Failed to read article to update the date after move");
-                    },
-                }
-                
-                self.rebuild_metadata(article_id,target);
-                Ok(())
+        self.index_mut().remove_from_list(article_id, origin);
+        self.remove_metadata(article_id, origin);
+        Ok(())
+    }
+
+    // Callers hold the article lock. The new date is saved before the move, a failed write
+    // leaves the article where it was
+    fn move_article (&self, article_id: &str, origin: ArticleStatus, target: ArticleStatus) -> Result<(), std::io::Error> {
+        let mut article = self.storage.read_article(article_id, origin)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, format!("Failed to read article {} to move it", article_id)))?;
+        article.update_date = chrono::offset::Utc::now();
+        self.save_article(article_id, &article, origin)?;
+
+        if let Err(error) = self.storage.move_article(article_id, origin, target) {
+            self.set_metadata(article_id, &article, origin);
+            return Err(error);
+        }
+        self.articles_cache.invalidate(article_id, origin);
+        self.articles_cache.invalidate(article_id, target);
+        self.set_metadata(article_id, &article, target);
+
+        {
+            let mut index = self.index_mut();
+            if !index.remove_from_list(article_id, origin) {
+                log::error!("Failed to find article_id index to remove it from the origin list");
+            }
+            index.add_to_list(article_id, target);
+        }
+        self.remove_metadata(article_id, origin);
+        Ok(())
+    }
+}
+
+impl ArticlesIndex {
+    fn list (&self, status: ArticleStatus) -> &Vec<String> {
+        match status {
+            ArticleStatus::Draft => &self.draft_list,
+            ArticleStatus::Published => &self.published_list,
+        }
+    }
+
+    fn list_mut (&mut self, status: ArticleStatus) -> &mut Vec<String> {
+        match status {
+            ArticleStatus::Draft => &mut self.draft_list,
+            ArticleStatus::Published => &mut self.published_list,
+        }
+    }
+
+    fn metadata_cache (&self, status: ArticleStatus) -> &MetadataCache {
+        match status {
+            ArticleStatus::Draft => &self.draft_metadata_cache,
+            ArticleStatus::Published => &self.published_metadata_cache,
+        }
+    }
+
+    fn metadata_cache_mut (&mut self, status: ArticleStatus) -> &mut MetadataCache {
+        match status {
+            ArticleStatus::Draft => &mut self.draft_metadata_cache,
+            ArticleStatus::Published => &mut self.published_metadata_cache,
+        }
+    }
+
+    pub fn is_listed (&self, article_id: &str, status: ArticleStatus) -> bool {
+        self.list(status).iter().any(|id| id == article_id)
+    }
+
+    fn add_to_list (&mut self, article_id: &str, status: ArticleStatus) {
+        if !self.is_listed(article_id, status) {
+            self.list_mut(status).push(article_id.to_string());
+        }
+    }
+
+    // Returns false when the article was not listed
+    fn remove_from_list (&mut self, article_id: &str, status: ArticleStatus) -> bool {
+        let list = self.list_mut(status);
+        match list.iter().position(|id| id == article_id) {
+            Some(id_index) => {
+                list.remove(id_index);
+                true
             },
-            Err(err) => Err(err),
+            None => false,
         }
     }
+
+    pub fn list_draft_articles (&self, start_index: usize, count: usize, sort: ListingSort, after: Option<&ListingCursor>) -> ArticleListing<'_> {
+        let articles = Self::listed_metadata(&self.draft_list, &self.draft_metadata_cache);
+        sort.paginate(articles, start_index, count, after)
+    }
+
+    pub fn list_published_articles (&self, start_index: usize, count: usize, sort: ListingSort, after: Option<&ListingCursor>) -> ArticleListing<'_> {
+        let articles = Self::listed_metadata(&self.published_list, &self.published_metadata_cache);
+        sort.paginate(articles, start_index, count, after)
+    }
+
+    pub fn published_metadata (&self, article_id: &str) -> Option<&ArticleMetadata> {
+        self.published_metadata_cache.get_metadata(article_id)
+    }
+
+    // The draft when there is one, as edited in the dashboard
+    pub fn latest_metadata (&self, article_id: &str) -> Option<&ArticleMetadata> {
+        self.draft_metadata_cache.get_metadata(article_id)
+            .or_else(|| self.published_metadata(article_id))
+    }
+
+    // Every published article with its metadata, newest first, for the public site pages
+    pub fn published_by_date (&self) -> Vec<(String, ArticleMetadata)> {
+        let sort = ListingSort { field: SortField::CreatedDate, order: SortOrder::Desc };
+        self.list_published_articles(0, usize::MAX, sort, None).articles.into_iter()
+            .map(|item| (item.id.to_string(), item.metadata.to_owned()))
+            .collect()
+    }
+
+    // List the latest version of the articles that pass the filter
+    pub fn query_articles (&self, filter: &ArticleFilter, start_index: usize, count: usize, sort: ListingSort, after: Option<&ListingCursor>) -> ArticleListing<'_> {
+        let drafts = Self::listed_metadata(&self.draft_list, &self.draft_metadata_cache);
+        let published = Self::listed_metadata(&self.published_list, &self.published_metadata_cache);
+
+        let articles: Vec<(&str, &ArticleMetadata)> = match filter.status {
+            Some(ListingStatus::Draft) => drafts.collect(),
+            Some(ListingStatus::DraftOnly) => drafts
+                .filter(|(article_id, _)| !self.published_metadata_cache.is_cached(article_id))
+                .collect(),
+            Some(ListingStatus::Changed) => drafts
+                .filter(|(article_id, _)| self.published_metadata_cache.is_cached(article_id))
+                .collect(),
+            Some(ListingStatus::Published) => published.collect(),
+            None => drafts
+                .chain(published.filter(|(article_id, _)| !self.draft_metadata_cache.is_cached(article_id)))
+                .collect(),
+        };
+
+        let articles = articles.into_iter().filter(|(_, metadata)| filter.matches(metadata));
+        sort.paginate(articles, start_index, count, after)
+    }
+
+    // Pair every listed article id with its cached metadata
+    fn listed_metadata<'a> (list: &'a [String], metadata_cache: &'a MetadataCache) -> impl Iterator<Item = (&'a str, &'a ArticleMetadata)> {
+        list.iter().filter_map(move |article_id| {
+            match metadata_cache.get_metadata(article_id) {
+                Some(metadata) => Some((article_id.as_str(), metadata)),
+                None => {
+                    log::error!("Failed to get metadata of article {} when listing articles", article_id);
+                    None
+                },
+            }
+        })
+    }
 }
 
 #[allow(dead_code)]
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ArticleStatus
 {
     Draft,
     Published,
 }
 
+impl ArticleStatus {
+    // Directory holding the articles with this status
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            ArticleStatus::Draft => "draft",
+            ArticleStatus::Published => "published",
+        }
+    }
+}
+
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum ArticleErrorKind
 {
-    ArticleAlreadyExist,
-    ArticleNotFound
+    ArticleNotFound,
+    // Unpublish or discard of an article that only has a draft
+    ArticleNotPublished,
+    // Publish or discard of a published article without changes
+    NoDraft,
+    // Reading or writing the stored article failed
+    Storage(std::io::Error)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ArticleError {
     kind: ArticleErrorKind
 }
 
+impl ArticleError {
+    pub fn new(kind: ArticleErrorKind) -> Self {
+        Self { kind }
+    }
+
+    pub fn kind(&self) -> &ArticleErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> ArticleErrorKind {
+        self.kind
+    }
+}
+
 impl fmt::Display for ArticleError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Article Error")
+        match &self.kind {
+            ArticleErrorKind::ArticleNotFound => write!(f, "Article not found"),
+            ArticleErrorKind::ArticleNotPublished => write!(f, "Article is not published"),
+            ArticleErrorKind::NoDraft => write!(f, "Article has no unpublished changes"),
+            ArticleErrorKind::Storage(error) => write!(f, "Article storage failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ArticleError {}
+
+impl From<std::io::Error> for ArticleError {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(ArticleErrorKind::Storage(error))
+    }
+}
+
+// For the command line, which reports io errors
+impl From<ArticleError> for std::io::Error {
+    fn from(error: ArticleError) -> Self {
+        match error.kind {
+            ArticleErrorKind::Storage(error) => error,
+            ArticleErrorKind::ArticleNotFound => std::io::Error::new(ErrorKind::NotFound, error.to_string()),
+            _ => std::io::Error::other(error.to_string()),
+        }
     }
 }
\ No newline at end of file
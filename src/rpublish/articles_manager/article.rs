@@ -1,12 +1,226 @@
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::rpublish::editorjs::{self, EditorDocument};
+use crate::error::FieldError;
+
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_EXCERPT_LENGTH: usize = 1000;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+// Bytes of the Editor.js json of an article
+pub const MAX_DATA_SIZE: usize = 2 * 1024 * 1024;
+// Bytes of an update request, the data is escaped inside the json body
+pub const MAX_UPDATE_SIZE: usize = 3 * MAX_DATA_SIZE;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Article{
     pub title: String,
     pub author: String,
     pub data: String,
     pub tags: Vec<String>,
     pub created_date: DateTime<Utc>,
-    pub update_date: DateTime<Utc>
-}
\ No newline at end of file
+    pub update_date: DateTime<Utc>,
+    // Explicit excerpt and cover, derived from the data when not set
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    #[serde(default)]
+    pub cover_image: Option<String>,
+    // Overrides for search engines and social sharing
+    #[serde(default)]
+    pub seo_title: Option<String>,
+    #[serde(default)]
+    pub seo_description: Option<String>,
+    #[serde(default)]
+    pub social_image: Option<String>,
+    #[serde(default)]
+    pub noindex: bool
+}
+
+impl Article {
+    pub fn apply(&mut self, changes: &ArticleUpdate) {
+        self.title = changes.title.to_owned();
+        self.data = changes.data.to_owned();
+        if let Some(excerpt) = &changes.excerpt {
+            self.excerpt = non_empty(excerpt);
+        }
+        if let Some(cover_image) = &changes.cover_image {
+            self.cover_image = non_empty(cover_image);
+        }
+        if let Some(seo_title) = &changes.seo_title {
+            self.seo_title = non_empty(seo_title);
+        }
+        if let Some(seo_description) = &changes.seo_description {
+            self.seo_description = non_empty(seo_description);
+        }
+        if let Some(social_image) = &changes.social_image {
+            self.social_image = non_empty(social_image);
+        }
+        if let Some(noindex) = changes.noindex {
+            self.noindex = noindex;
+        }
+    }
+}
+
+// Changes sent by the editor, optional fields are kept as they are when missing
+// and cleared when empty
+#[derive(Serialize, Deserialize)]
+pub struct ArticleUpdate {
+    pub title: String,
+    pub data: String,
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    #[serde(default)]
+    pub cover_image: Option<String>,
+    #[serde(default)]
+    pub seo_title: Option<String>,
+    #[serde(default)]
+    pub seo_description: Option<String>,
+    #[serde(default)]
+    pub social_image: Option<String>,
+    #[serde(default)]
+    pub noindex: Option<bool>
+}
+
+impl ArticleUpdate {
+    // Trim the text fields and check them, the data has to be an Editor.js document of known blocks.
+    // Valid data is stored as it is serialized again, without the unknown top level fields
+    pub fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        self.title = self.title.trim().to_string();
+        if self.title.is_empty() {
+            errors.push(FieldError::new("title", "The article needs a title"));
+        } else if self.title.chars().count() > MAX_TITLE_LENGTH {
+            errors.push(FieldError::new("title", &format!("Longer than {} characters", MAX_TITLE_LENGTH)));
+        }
+
+        if self.data.len() > MAX_DATA_SIZE {
+            errors.push(FieldError::new("data", &format!("Larger than {} MB", MAX_DATA_SIZE / 1024 / 1024)));
+        } else if !self.data.trim().is_empty() {
+            match EditorDocument::parse(&self.data) {
+                Ok(document) => {
+                    let document_errors = document.validate();
+                    if document_errors.is_empty() {
+                        if let Ok(data) = serde_json::to_string(&document) {
+                            self.data = data;
+                        }
+                    }
+                    errors.extend(document_errors);
+                },
+                Err(error) => errors.push(FieldError::new("data", &format!("Not an Editor.js document: {}", error))),
+            }
+        }
+
+        for (field, value, max_length) in [
+            ("excerpt", &self.excerpt, MAX_EXCERPT_LENGTH),
+            ("seo_title", &self.seo_title, MAX_TITLE_LENGTH),
+            ("seo_description", &self.seo_description, MAX_DESCRIPTION_LENGTH),
+        ] {
+            if value.as_ref().is_some_and(|value| value.trim().chars().count() > max_length) {
+                errors.push(FieldError::new(field, &format!("Longer than {} characters", max_length)));
+            }
+        }
+        for (field, value) in [("cover_image", &self.cover_image), ("social_image", &self.social_image)] {
+            if value.as_deref().map(str::trim).is_some_and(|url| !url.is_empty() && !editorjs::is_link(url)) {
+                errors.push(FieldError::new(field, "Needs an http(s) or site url"));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(title: &str, data: &str) -> ArticleUpdate {
+        ArticleUpdate {
+            title: title.to_string(),
+            data: data.to_string(),
+            excerpt: None,
+            cover_image: None,
+            seo_title: None,
+            seo_description: None,
+            social_image: None,
+            noindex: None
+        }
+    }
+
+    fn document(blocks: Vec<serde_json::Value>) -> String {
+        json!({ "time": 1, "blocks": blocks, "version": "2.22.2" }).to_string()
+    }
+
+    fn paragraph(text: &str) -> serde_json::Value {
+        json!({ "type": "paragraph", "data": { "text": text } })
+    }
+
+    fn error_fields(mut update: ArticleUpdate) -> Vec<String> {
+        match update.validate() {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_update_is_trimmed_and_accepted() {
+        let mut valid = update("  A title  ", &document(vec![paragraph("Hello")]));
+        valid.excerpt = Some(String::from("Short"));
+        valid.cover_image = Some(String::from("/uploads/images/cover.png"));
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.title, "A title");
+    }
+
+    #[test]
+    fn title_is_required_up_to_its_limit() {
+        assert_eq!(error_fields(update("   ", "")), vec!["title"]);
+        assert_eq!(error_fields(update(&"a".repeat(MAX_TITLE_LENGTH), "")), Vec::<String>::new());
+        assert_eq!(error_fields(update(&"a".repeat(MAX_TITLE_LENGTH + 1), "")), vec!["title"]);
+    }
+
+    #[test]
+    fn data_over_the_size_limit_is_refused() {
+        let data = document(vec![paragraph(&"a".repeat(MAX_DATA_SIZE))]);
+        assert_eq!(error_fields(update("Title", &data)), vec!["data"]);
+    }
+
+    #[test]
+    fn block_count_and_block_size_are_limited() {
+        let blocks = (0..5001).map(|_| paragraph("a")).collect();
+        assert_eq!(error_fields(update("Title", &document(blocks))), vec!["data.blocks"]);
+
+        let data = document(vec![paragraph("a"), paragraph(&"a".repeat(256 * 1024))]);
+        assert_eq!(error_fields(update("Title", &data)), vec!["data.blocks[1]"]);
+    }
+
+    #[test]
+    fn unknown_blocks_and_malformed_data_are_refused() {
+        let data = document(vec![json!({ "type": "script", "data": {} })]);
+        assert_eq!(error_fields(update("Title", &data)), vec!["data.blocks[0].type"]);
+        assert_eq!(error_fields(update("Title", "{\"blocks\": 1}")), vec!["data"]);
+    }
+
+    #[test]
+    fn optional_fields_have_length_and_link_limits() {
+        let mut invalid = update("Title", "");
+        invalid.excerpt = Some("a".repeat(MAX_EXCERPT_LENGTH + 1));
+        invalid.seo_title = Some("a".repeat(MAX_TITLE_LENGTH + 1));
+        invalid.seo_description = Some("a".repeat(MAX_DESCRIPTION_LENGTH + 1));
+        invalid.cover_image = Some(String::from("javascript:alert(1)"));
+        invalid.social_image = Some(String::from("ftp://example.com/image.png"));
+        assert_eq!(
+            error_fields(invalid),
+            vec!["excerpt", "seo_title", "seo_description", "cover_image", "social_image"]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use std::cmp::Ordering;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::rpublish::metadata_cache::ArticleMetadata;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField
+{
+    #[default]
+    UpdateDate,
+    CreatedDate,
+    Title,
+    Author,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder
+{
+    Asc,
+    #[default]
+    Desc,
+}
+
+// Value used to order articles, text keys are compared case insensitive
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+enum SortKey
+{
+    Date(DateTime<Utc>),
+    Text(String),
+}
+
+impl SortKey {
+    fn from_metadata(field: SortField, metadata: &ArticleMetadata) -> Self {
+        match field {
+            SortField::UpdateDate => SortKey::Date(metadata.update_date),
+            SortField::CreatedDate => SortKey::Date(metadata.created_date),
+            SortField::Title => SortKey::Text(metadata.title.to_lowercase()),
+            SortField::Author => SortKey::Text(metadata.author.to_lowercase()),
+        }
+    }
+
+    fn parse(field: SortField, value: &str) -> Option<Self> {
+        match field {
+            SortField::UpdateDate | SortField::CreatedDate => {
+                value.parse::<i64>().ok().map(|nanos| SortKey::Date(Utc.timestamp_nanos(nanos)))
+            },
+            SortField::Title | SortField::Author => Some(SortKey::Text(value.to_string())),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            SortKey::Date(date) => date.timestamp_nanos().to_string(),
+            SortKey::Text(text) => text.to_owned(),
+        }
+    }
+}
+
+// Position of the last article returned in a page, used to continue listing after it
+// even if articles were added or removed in the meantime
+pub struct ListingCursor {
+    key: SortKey,
+    id: String
+}
+
+impl ListingCursor {
+    // Cursors are encoded as "{article_id}:{sort_key}"
+    pub fn parse(field: SortField, cursor: &str) -> Option<Self> {
+        let (id, key) = cursor.split_once(':')?;
+        Some(Self {
+            key: SortKey::parse(field, key)?,
+            id: id.to_string()
+        })
+    }
+
+    fn encode(key: &SortKey, id: &str) -> String {
+        format!("{}:{}", id, key.encode())
+    }
+}
+
+#[derive(Serialize)]
+pub struct ArticleListItem<'a> {
+    pub id: &'a str,
+    #[serde(flatten)]
+    pub metadata: &'a ArticleMetadata
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct ListingSort {
+    pub field: SortField,
+    pub order: SortOrder
+}
+
+pub struct ArticleListing<'a> {
+    pub articles: Vec<ArticleListItem<'a>>,
+    pub total: usize,
+    pub next_cursor: Option<String>
+}
+
+impl ListingSort {
+    fn compare(&self, a: (&SortKey, &str), b: (&SortKey, &str)) -> Ordering {
+        // The article id breaks ties so the order is always the same
+        let ordering = a.0.cmp(b.0).then_with(|| a.1.cmp(b.1));
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+
+    // Sort the given articles and return a page of them. start_index counts from the
+    // article after the cursor if any, from the first article otherwise
+    pub fn paginate<'a, I>(&self, articles: I, start_index: usize, count: usize, after: Option<&ListingCursor>) -> ArticleListing<'a>
+    where
+        I: Iterator<Item = (&'a str, &'a ArticleMetadata)>
+    {
+        let mut keyed: Vec<(SortKey, &'a str, &'a ArticleMetadata)> = articles
+            .map(|(id, metadata)| (SortKey::from_metadata(self.field, metadata), id, metadata))
+            .collect();
+        keyed.sort_by(|a, b| self.compare((&a.0, a.1), (&b.0, b.1)));
+
+        let total = keyed.len();
+        let start = match after {
+            Some(cursor) => keyed.partition_point(|item| {
+                self.compare((&item.0, item.1), (&cursor.key, cursor.id.as_str())) != Ordering::Greater
+            }),
+            None => 0,
+        }.saturating_add(start_index).min(total);
+        let end = start.saturating_add(count).min(total);
+
+        let page = &keyed[start..end];
+        let next_cursor = match page.last() {
+            Some(last) if end < total => Some(ListingCursor::encode(&last.0, last.1)),
+            _ => None,
+        };
+
+        ArticleListing {
+            articles: page.iter()
+                .map(|(_, id, metadata)| ArticleListItem { id, metadata })
+                .collect(),
+            total,
+            next_cursor
+        }
+    }
+}
+
+// Which version of the articles is listed when filtering
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingStatus
+{
+    // Every article with a draft version, published or not
+    Draft,
+    // Drafts that were never published
+    DraftOnly,
+    // Published articles with unpublished changes in their draft
+    Changed,
+    Published,
+}
+
+#[derive(Default)]
+pub struct ArticleFilter {
+    pub status: Option<ListingStatus>,
+    pub author: Option<String>,
+    pub tag: Option<String>,
+    pub title: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>
+}
+
+impl ArticleFilter {
+    // Check the metadata against every filter that is set, text comparisons ignore case
+    pub fn matches(&self, metadata: &ArticleMetadata) -> bool {
+        if let Some(author) = &self.author {
+            if metadata.author.to_lowercase() != author.to_lowercase() {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            let tag = tag.to_lowercase();
+            if !metadata.tags.iter().any(|article_tag| article_tag.to_lowercase() == tag) {
+                return false;
+            }
+        }
+        if let Some(title) = &self.title {
+            if !metadata.title.to_lowercase().contains(&title.to_lowercase()) {
+                return false;
+            }
+        }
+        Self::in_range(&metadata.created_date, &self.created_after, &self.created_before)
+            && Self::in_range(&metadata.update_date, &self.updated_after, &self.updated_before)
+    }
+
+    fn in_range(date: &DateTime<Utc>, after: &Option<DateTime<Utc>>, before: &Option<DateTime<Utc>>) -> bool {
+        after.is_none_or(|after| date >= &after) && before.is_none_or(|before| date <= &before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(title: &str, minute: u32) -> ArticleMetadata {
+        let date = Utc.ymd(2024, 1, 1).and_hms(0, minute, 0);
+        ArticleMetadata {
+            version: 0,
+            title: title.to_string(),
+            author: String::from("alice"),
+            tags: Vec::new(),
+            created_date: date,
+            update_date: date,
+            excerpt: String::new(),
+            cover_image: None,
+            word_count: 0,
+            reading_time: 0,
+            noindex: false
+        }
+    }
+
+    fn ids(listing: &ArticleListing) -> Vec<String> {
+        listing.articles.iter().map(|item| item.id.to_string()).collect()
+    }
+
+    #[test]
+    fn cursor_round_trips_dates_and_text() {
+        let date = Utc.ymd(2024, 5, 6).and_hms(7, 8, 9);
+        let encoded = ListingCursor::encode(&SortKey::Date(date), "a1");
+        let cursor = ListingCursor::parse(SortField::UpdateDate, &encoded).unwrap();
+        assert_eq!(cursor.id, "a1");
+        assert_eq!(cursor.key, SortKey::Date(date));
+
+        // Only the first colon separates the id, titles may contain more
+        let encoded = ListingCursor::encode(&SortKey::Text(String::from("a: b")), "a2");
+        let cursor = ListingCursor::parse(SortField::Title, &encoded).unwrap();
+        assert_eq!(cursor.id, "a2");
+        assert_eq!(cursor.key, SortKey::Text(String::from("a: b")));
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        assert!(ListingCursor::parse(SortField::UpdateDate, "no-separator").is_none());
+        assert!(ListingCursor::parse(SortField::CreatedDate, "id:not-a-date").is_none());
+    }
+
+    #[test]
+    fn pages_continue_after_the_cursor_when_articles_are_added() {
+        let a = metadata("a", 1);
+        let b = metadata("b", 2);
+        let c = metadata("c", 3);
+        let d = metadata("d", 4);
+        let sort = ListingSort::default();
+
+        let first = sort.paginate(vec![("a", &a), ("b", &b), ("c", &c)].into_iter(), 0, 2, None);
+        assert_eq!(ids(&first), ["c", "b"]);
+        let cursor = ListingCursor::parse(sort.field, first.next_cursor.as_deref().unwrap()).unwrap();
+
+        // A newer article lands on the first page and doesn't push "b" to the next one
+        let second = sort.paginate(vec![("a", &a), ("b", &b), ("c", &c), ("d", &d)].into_iter(), 0, 2, Some(&cursor));
+        assert_eq!(ids(&second), ["a"]);
+        assert_eq!(second.total, 4);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn start_index_counts_from_the_cursor() {
+        let items: Vec<ArticleMetadata> = (0..6).map(|minute| metadata("t", minute)).collect();
+        let ids_list: Vec<String> = (0..6).map(|index| format!("a{}", index)).collect();
+        let articles = || ids_list.iter().map(String::as_str).zip(items.iter());
+        let sort = ListingSort { field: SortField::CreatedDate, order: SortOrder::Asc };
+
+        let first = sort.paginate(articles(), 0, 2, None);
+        let cursor = ListingCursor::parse(sort.field, first.next_cursor.as_deref().unwrap()).unwrap();
+        let third = sort.paginate(articles(), 2, 2, Some(&cursor));
+        assert_eq!(ids(&third), ["a4", "a5"]);
+
+        let past_the_end = sort.paginate(articles(), 10, 2, Some(&cursor));
+        assert!(past_the_end.articles.is_empty());
+    }
+
+    #[test]
+    fn equal_keys_are_ordered_by_id() {
+        let a = metadata("Same", 1);
+        let b = metadata("same", 1);
+        let sort = ListingSort { field: SortField::Title, order: SortOrder::Asc };
+        let listing = sort.paginate(vec![("b", &b), ("a", &a)].into_iter(), 0, 10, None);
+        assert_eq!(ids(&listing), ["a", "b"]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Result, Write};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::locks::MutexRecover;
+
+// Append only record of the editorial and security actions, one json entry per line.
+// It is never rotated, entries are kept until the file is removed by hand
+pub const AUDIT_PATH: &str = "data/logs/audit.log";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    ArticleCreate,
+    ArticleUpdate,
+    ArticlePublish,
+    ArticleUnpublish,
+    ArticleDiscard,
+    ArticleDelete,
+    ArticleRestore,
+    UserCreate,
+    UserPassword,
+    UserDisable,
+    UserEnable,
+    PermissionGrant,
+    PermissionRevoke,
+    SessionRevoke,
+    SettingsUpdate,
+    HistoryPush,
+}
+
+impl AuditAction {
+    pub fn name(&self) -> String {
+        serde_json::to_value(self).ok()
+            .and_then(|value| value.as_str().map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub date: DateTime<Utc>,
+    pub action: AuditAction,
+    // User that did the action, the attempted username for failed logins
+    pub actor: String,
+    // None for actions run from the command line
+    pub ip: Option<String>,
+    // Article id, username or session the action was done on
+    pub target: Option<String>,
+    // Permission changed, reason of a failed login...
+    pub detail: Option<String>
+}
+
+// Every condition is optional, entries are returned newest first
+#[derive(Deserialize, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().is_none_or(|actor| *actor == entry.actor)
+            && self.action.is_none_or(|action| action == entry.action)
+            && self.target.as_ref().is_none_or(|target| entry.target.as_ref() == Some(target))
+            && self.ip.as_ref().is_none_or(|ip| entry.ip.as_ref().is_some_and(|entry_ip| entry_ip.starts_with(ip.as_str())))
+            && self.since.is_none_or(|since| entry.date >= since)
+            && self.until.is_none_or(|until| entry.date <= until)
+    }
+}
+
+pub struct AuditLog {
+    file: Mutex<File>
+}
+
+impl AuditLog {
+    pub fn open() -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(AUDIT_PATH)?;
+        Ok(Self {
+            file: Mutex::new(file)
+        })
+    }
+
+    // Failing to record doesn't stop the action, the error goes to the server log
+    pub fn record(&self, action: AuditAction, actor: &str, ip: Option<&str>, target: Option<&str>, detail: Option<&str>) {
+        let entry = AuditEntry {
+            date: Utc::now(),
+            action,
+            actor: actor.to_string(),
+            ip: ip.map(String::from),
+            target: target.map(String::from),
+            detail: detail.map(String::from)
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(error) => {
+                log::error!("Failed to serialize audit entry: {}", error);
+                return;
+            },
+        };
+        let mut file = self.file.lock_or_recover();
+        if let Err(error) = file.write_all(format!("{}\n", line).as_bytes()) {
+            log::error!("Failed to write {}: {}", AUDIT_PATH, error);
+        }
+    }
+
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let file = match File::open(AUDIT_PATH) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                Ok(_) => {},
+                // A line cut by a crash, the next ones are still readable
+                Err(_) => log::warn!("Skipping unreadable audit entry: {}", line),
+            }
+        }
+        entries.reverse();
+        if let Some(limit) = filter.limit {
+            entries.truncate(limit);
+        }
+        Ok(entries)
+    }
+}
+
+// Spreadsheet friendly export, fields with commas, quotes or line breaks are quoted. Failed
+// logins record any username, values that a spreadsheet would run as a formula get a quote
+pub fn to_csv(entries: &[AuditEntry]) -> String {
+    fn field(value: &str) -> String {
+        let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+            format!("'{}", value)
+        } else {
+            value.to_string()
+        };
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value
+        }
+    }
+    let mut csv = String::from("date,action,actor,ip,target,detail\n");
+    for entry in entries {
+        csv.push_str(&[
+            entry.date.to_rfc3339(),
+            entry.action.name(),
+            field(&entry.actor),
+            field(entry.ip.as_deref().unwrap_or_default()),
+            field(entry.target.as_deref().unwrap_or_default()),
+            field(entry.detail.as_deref().unwrap_or_default()),
+        ].join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(action: AuditAction, actor: &str, ip: Option<&str>, target: Option<&str>) -> AuditEntry {
+        AuditEntry {
+            date: Utc.ymd(2026, 10, 1).and_hms(12, 0, 0),
+            action,
+            actor: actor.to_string(),
+            ip: ip.map(String::from),
+            target: target.map(String::from),
+            detail: None
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_every_entry() {
+        assert!(AuditFilter::default().matches(&entry(AuditAction::Login, "alice", None, None)));
+    }
+
+    #[test]
+    fn filter_conditions_are_all_required() {
+        let login = entry(AuditAction::Login, "alice", Some("192.168.1.20"), None);
+        let publish = entry(AuditAction::ArticlePublish, "alice", None, Some("article-1"));
+        let filter = AuditFilter {
+            actor: Some(String::from("alice")),
+            action: Some(AuditAction::Login),
+            ..AuditFilter::default()
+        };
+        assert!(filter.matches(&login));
+        assert!(!filter.matches(&publish));
+
+        let filter = AuditFilter { target: Some(String::from("article-1")), ..AuditFilter::default() };
+        assert!(filter.matches(&publish));
+        assert!(!filter.matches(&login));
+    }
+
+    #[test]
+    fn ip_filter_is_a_prefix_and_skips_command_line_entries() {
+        let filter = AuditFilter { ip: Some(String::from("192.168.")), ..AuditFilter::default() };
+        assert!(filter.matches(&entry(AuditAction::Login, "alice", Some("192.168.1.20"), None)));
+        assert!(!filter.matches(&entry(AuditAction::Login, "alice", Some("10.0.0.1"), None)));
+        assert!(!filter.matches(&entry(AuditAction::UserCreate, "rpublish", None, None)));
+    }
+
+    #[test]
+    fn date_range_includes_its_limits() {
+        let login = entry(AuditAction::Login, "alice", None, None);
+        let filter = AuditFilter { since: Some(login.date), until: Some(login.date), ..AuditFilter::default() };
+        assert!(filter.matches(&login));
+        let filter = AuditFilter { since: Some(Utc.ymd(2026, 10, 2).and_hms(0, 0, 0)), ..AuditFilter::default() };
+        assert!(!filter.matches(&login));
+        let filter = AuditFilter { until: Some(Utc.ymd(2026, 9, 30).and_hms(0, 0, 0)), ..AuditFilter::default() };
+        assert!(!filter.matches(&login));
+    }
+
+    #[test]
+    fn csv_escapes_formulas_and_quotes() {
+        let mut failed_login = entry(AuditAction::LoginFailed, "=HYPERLINK(\"http://x\",\"y\")", Some("10.0.0.1"), None);
+        failed_login.detail = Some(String::from("-2+3"));
+        let csv = to_csv(&[failed_login, entry(AuditAction::Login, "alice", Some("10.0.0.2"), None)]);
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], "date,action,actor,ip,target,detail");
+        assert_eq!(lines[1], "2026-10-01T12:00:00+00:00,login_failed,\"'=HYPERLINK(\"\"http://x\"\",\"\"y\"\")\",10.0.0.1,,'-2+3");
+        assert_eq!(lines[2], "2026-10-01T12:00:00+00:00,login,alice,10.0.0.2,,");
+    }
+}
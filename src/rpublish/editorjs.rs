@@ -0,0 +1,423 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::error::FieldError;
+use crate::settings;
+
+// Average adult reading speed used to estimate reading time
+const WORDS_PER_MINUTE: usize = 200;
+
+// Blocks rendered by render_html, documents with other blocks are refused
+const KNOWN_BLOCKS: [&str; 15] = [
+    "paragraph", "header", "list", "nested_list", "checklist", "quote", "warning", "alert",
+    "code", "raw", "delimiter", "table", "image", "embed", "attaches"
+];
+const MAX_BLOCKS: usize = 5000;
+// Bytes of the json of a single block
+const MAX_BLOCK_SIZE: usize = 256 * 1024;
+
+// Editor.js output as stored in the article data
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct EditorDocument {
+    #[serde(default)]
+    pub time: Option<i64>,
+    #[serde(default)]
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub version: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Block {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub block_type: String,
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tunes: Option<Value>
+}
+
+impl EditorDocument {
+    // Parse the article data, new articles have an empty string as data
+    pub fn parse(data: &str) -> Result<Self, serde_json::Error> {
+        if data.trim().is_empty() {
+            Ok(Self::default())
+        } else {
+            serde_json::from_str(data)
+        }
+    }
+
+    // Parse the article data ignoring malformed documents
+    pub fn parse_lossy(data: &str) -> Self {
+        Self::parse(data).unwrap_or_default()
+    }
+
+    // Plain text of the paragraphs joined until the length is reached, cut at a word boundary
+    pub fn excerpt(&self, max_length: usize) -> String {
+        let mut excerpt = String::new();
+        for block in self.blocks.iter().filter(|block| block.block_type == "paragraph") {
+            let text = block.text().join(" ");
+            if text.is_empty() {
+                continue;
+            }
+            if !excerpt.is_empty() {
+                excerpt.push(' ');
+            }
+            excerpt.push_str(&text);
+            if excerpt.chars().count() >= max_length {
+                break;
+            }
+        }
+        truncate_words(&excerpt, max_length)
+    }
+
+    // Url of the first image block
+    pub fn first_image(&self) -> Option<String> {
+        self.blocks.iter()
+            .filter(|block| block.block_type == "image")
+            .find_map(|block| block.image_url())
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.blocks.iter()
+            .flat_map(|block| block.text())
+            .map(|text| text.split_whitespace().count())
+            .sum()
+    }
+
+    // Estimated reading time in minutes, any text takes at least a minute
+    pub fn reading_time(&self) -> usize {
+        let words = self.word_count();
+        words.div_ceil(WORDS_PER_MINUTE)
+    }
+
+    // Problems of every block, the fields are named like "data.blocks[2].data.text"
+    pub fn validate(&self) -> Vec<FieldError> {
+        if self.blocks.len() > MAX_BLOCKS {
+            return vec![FieldError::new("data.blocks", &format!("More than {} blocks", MAX_BLOCKS))];
+        }
+        self.blocks.iter().enumerate()
+            .flat_map(|(index, block)| block.validate(&format!("data.blocks[{}]", index)))
+            .collect()
+    }
+
+    // Render the blocks to html, text fields keep the inline markup written in the editor
+    pub fn render_html(&self) -> String {
+        let mut html = String::new();
+        for block in &self.blocks {
+            html.push_str(&block.render_html());
+            html.push('\n');
+        }
+        html
+    }
+}
+
+impl Block {
+    fn validate(&self, field: &str) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !KNOWN_BLOCKS.contains(&self.block_type.as_str()) {
+            errors.push(FieldError::new(&format!("{}.type", field), &format!("Unknown block type {}", self.block_type)));
+            return errors;
+        }
+        let data = match &self.data {
+            Value::Object(data) => data,
+            _ => {
+                errors.push(FieldError::new(&format!("{}.data", field), "Must be an object"));
+                return errors;
+            },
+        };
+        if serde_json::to_string(data).map(|json| json.len()).unwrap_or(0) > MAX_BLOCK_SIZE {
+            errors.push(FieldError::new(field, &format!("Larger than {} KB", MAX_BLOCK_SIZE / 1024)));
+        }
+
+        let mut expect = |key: &str, valid: bool, message: &str| {
+            if !valid {
+                errors.push(FieldError::new(&format!("{}.data.{}", field, key), message));
+            }
+        };
+        // Missing optional text is rendered empty, text of the wrong type is refused
+        let text = |key: &str| data.get(key).is_none_or(Value::is_string);
+        match self.block_type.as_str() {
+            "paragraph" => expect("text", text("text"), "Must be text"),
+            "header" => {
+                expect("text", text("text"), "Must be text");
+                expect("level", data.get("level").is_none_or(|level| level.as_u64().is_some_and(|level| (1..=6).contains(&level))), "Must be between 1 and 6");
+            },
+            "list" | "nested_list" | "checklist" => expect("items", data.get("items").is_none_or(Value::is_array), "Must be a list"),
+            "quote" => {
+                expect("text", text("text"), "Must be text");
+                expect("caption", text("caption"), "Must be text");
+            },
+            "warning" => {
+                expect("title", text("title"), "Must be text");
+                expect("message", text("message"), "Must be text");
+            },
+            "alert" => {
+                expect("type", text("type"), "Must be text");
+                expect("message", text("message"), "Must be text");
+            },
+            "code" => expect("code", text("code"), "Must be text"),
+            "raw" => expect("html", text("html"), "Must be text"),
+            "table" => expect(
+                "content",
+                data.get("content").is_none_or(|rows| rows.as_array().is_some_and(|rows| rows.iter().all(Value::is_array))),
+                "Must be a list of rows"
+            ),
+            "image" => {
+                expect("caption", text("caption"), "Must be text");
+                expect("file.url", self.image_url().is_some_and(|url| is_link(&url)), "Needs an http(s) or site url");
+            },
+            "embed" => expect("embed", data["embed"].as_str().is_some_and(settings::is_web_url), "Needs an http(s) url"),
+            "attaches" => expect("file.url", data["file"]["url"].as_str().is_some_and(is_link), "Needs an http(s) or site url"),
+            _ => {},
+        }
+        errors
+    }
+
+    // Readable text of the block without markup, code and raw html are not counted as text
+    pub fn text(&self) -> Vec<String> {
+        let mut texts: Vec<String> = Vec::new();
+        match self.block_type.as_str() {
+            "paragraph" | "header" => push_text(&mut texts, &self.data["text"]),
+            "quote" => {
+                push_text(&mut texts, &self.data["text"]);
+                push_text(&mut texts, &self.data["caption"]);
+            },
+            "warning" => {
+                push_text(&mut texts, &self.data["title"]);
+                push_text(&mut texts, &self.data["message"]);
+            },
+            "alert" => push_text(&mut texts, &self.data["message"]),
+            "list" | "nested_list" => push_list_items(&mut texts, &self.data["items"]),
+            "checklist" => {
+                if let Value::Array(items) = &self.data["items"] {
+                    for item in items {
+                        push_text(&mut texts, &item["text"]);
+                    }
+                }
+            },
+            "table" => {
+                if let Value::Array(rows) = &self.data["content"] {
+                    for row in rows {
+                        if let Value::Array(cells) = row {
+                            for cell in cells {
+                                push_text(&mut texts, cell);
+                            }
+                        }
+                    }
+                }
+            },
+            "image" => push_text(&mut texts, &self.data["caption"]),
+            _ => {}
+        }
+        texts
+    }
+
+    // Image tool stores the url in file.url, simple image in url
+    pub fn image_url(&self) -> Option<String> {
+        self.data["file"]["url"].as_str()
+            .or_else(|| self.data["url"].as_str())
+            .filter(|url| !url.is_empty())
+            .map(|url| url.to_string())
+    }
+
+    fn render_html(&self) -> String {
+        let data = &self.data;
+        match self.block_type.as_str() {
+            "paragraph" => format!("<p>{}</p>", inline(&data["text"])),
+            "header" => {
+                let level = data["level"].as_u64().unwrap_or(2).clamp(1, 6);
+                format!("<h{level}>{}</h{level}>", inline(&data["text"]), level = level)
+            },
+            "list" | "nested_list" => render_list(&data["items"], data["style"].as_str() == Some("ordered")),
+            "checklist" => {
+                let mut html = String::from("<ul class=\"checklist\">");
+                if let Value::Array(items) = &data["items"] {
+                    for item in items {
+                        let checked = if item["checked"].as_bool().unwrap_or(false) {" checked"} else {""};
+                        html.push_str(&format!(
+                            "<li><input type=\"checkbox\" disabled{}> {}</li>", checked, inline(&item["text"])
+                        ));
+                    }
+                }
+                html.push_str("</ul>");
+                html
+            },
+            "quote" => format!(
+                "<blockquote><p>{}</p><cite>{}</cite></blockquote>",
+                inline(&data["text"]), inline(&data["caption"])
+            ),
+            "warning" => format!(
+                "<div class=\"warning\"><strong>{}</strong><p>{}</p></div>",
+                inline(&data["title"]), inline(&data["message"])
+            ),
+            "alert" => format!(
+                "<div class=\"alert alert-{}\">{}</div>",
+                escape_html(data["type"].as_str().unwrap_or("info")), inline(&data["message"])
+            ),
+            "code" => format!("<pre><code>{}</code></pre>", escape_html(data["code"].as_str().unwrap_or(""))),
+            "raw" => data["html"].as_str().unwrap_or("").to_string(),
+            "delimiter" => String::from("<hr>"),
+            "table" => render_table(data),
+            "image" => match self.image_url() {
+                Some(url) => format!(
+                    "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>",
+                    escape_html(&url), escape_html(&strip_html(data["caption"].as_str().unwrap_or(""))), inline(&data["caption"])
+                ),
+                None => String::new(),
+            },
+            "embed" => match data["embed"].as_str() {
+                Some(embed) => format!(
+                    "<figure class=\"embed\"><iframe src=\"{}\" width=\"{}\" height=\"{}\" frameborder=\"0\" allowfullscreen></iframe><figcaption>{}</figcaption></figure>",
+                    escape_html(embed),
+                    data["width"].as_u64().unwrap_or(580),
+                    data["height"].as_u64().unwrap_or(320),
+                    inline(&data["caption"])
+                ),
+                None => String::new(),
+            },
+            "attaches" => match data["file"]["url"].as_str() {
+                Some(url) => {
+                    let name = data["title"].as_str()
+                        .or_else(|| data["file"]["name"].as_str())
+                        .unwrap_or(url);
+                    format!("<p class=\"attachment\"><a href=\"{}\" download>{}</a></p>", escape_html(url), escape_html(name))
+                },
+                None => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+}
+
+// Absolute http(s) urls and paths of the site like the uploads, no javascript: or data: urls
+pub fn is_link(url: &str) -> bool {
+    settings::is_web_url(url) || (url.starts_with('/') && !url.starts_with("//") && !url.contains(char::is_whitespace))
+}
+
+fn push_text(texts: &mut Vec<String>, value: &Value) {
+    if let Some(text) = value.as_str() {
+        let text = strip_html(text);
+        if !text.is_empty() {
+            texts.push(text);
+        }
+    }
+}
+
+// List items are strings, nested list items are objects with content and children items
+fn push_list_items(texts: &mut Vec<String>, items: &Value) {
+    if let Value::Array(items) = items {
+        for item in items {
+            match item {
+                Value::String(_) => push_text(texts, item),
+                Value::Object(_) => {
+                    push_text(texts, &item["content"]);
+                    push_list_items(texts, &item["items"]);
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+// Remove the inline markup editor.js leaves in text fields and decode basic entities
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for character in html.chars() {
+        match character {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                // Tags like <br> separate words
+                text.push(' ');
+            },
+            _ if !in_tag => text.push(character),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Cut the text to the max length without splitting words
+pub fn truncate_words(text: &str, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_length).collect();
+    let cut = match cut.rfind(' ') {
+        Some(index) if index > 0 => &cut[..index],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace()))
+}
+
+fn inline(value: &Value) -> &str {
+    value.as_str().unwrap_or("")
+}
+
+fn render_list(items: &Value, ordered: bool) -> String {
+    let tag = if ordered {"ol"} else {"ul"};
+    let mut html = format!("<{}>", tag);
+    if let Value::Array(items) = items {
+        for item in items {
+            match item {
+                Value::String(text) => html.push_str(&format!("<li>{}</li>", text)),
+                Value::Object(_) => {
+                    html.push_str(&format!("<li>{}", inline(&item["content"])));
+                    if item["items"].as_array().is_some_and(|children| !children.is_empty()) {
+                        html.push_str(&render_list(&item["items"], ordered));
+                    }
+                    html.push_str("</li>");
+                },
+                _ => {}
+            }
+        }
+    }
+    html.push_str(&format!("</{}>", tag));
+    html
+}
+
+fn render_table(data: &Value) -> String {
+    let mut html = String::from("<table>");
+    if let Value::Array(rows) = &data["content"] {
+        let with_headings = data["withHeadings"].as_bool().unwrap_or(false);
+        for (index, row) in rows.iter().enumerate() {
+            let cell_tag = if with_headings && index == 0 {"th"} else {"td"};
+            html.push_str("<tr>");
+            if let Value::Array(cells) = row {
+                for cell in cells {
+                    html.push_str(&format!("<{tag}>{}</{tag}>", inline(cell), tag = cell_tag));
+                }
+            }
+            html.push_str("</tr>");
+        }
+    }
+    html.push_str("</table>");
+    html
+}
+
+// Escape text to be placed in html content or attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
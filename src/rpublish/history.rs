@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Mutex;
+use serde::Serialize;
+
+use crate::locks::MutexRecover;
+use super::storage::is_safe_id;
+
+const REPOSITORY_PATH: &str = "data";
+
+// Only the draft and published articles are versioned
+const GITIGNORE: &str = "/*
+!/.gitignore
+!/articles/
+/articles/*
+!/articles/draft/
+!/articles/published/
+*.tmp
+";
+
+// Article history kept in a git repository at data/, every change made from the dashboard
+// is committed with the editor as author. Uses the git command line
+pub struct GitHistory {
+    // Commits stage and commit in two steps, one at a time
+    lock: Mutex<()>,
+    // Url or path of the repository the history is pushed to
+    remote: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub commit: String,
+    pub author: String,
+    pub date: String,
+    pub message: String
+}
+
+impl GitHistory {
+    // Create the repository on first use and commit the articles already there
+    pub fn open(remote: Option<String>) -> Result<Self> {
+        let history = Self {
+            lock: Mutex::new(()),
+            remote
+        };
+
+        if !Path::new(REPOSITORY_PATH).join(".git").exists() {
+            Self::git(&["init", "--quiet"])?;
+            log::info!("Git repository created in {}", REPOSITORY_PATH);
+        }
+        fs::write(Path::new(REPOSITORY_PATH).join(".gitignore"), GITIGNORE)?;
+
+        if history.has_changes(&["."])? {
+            Self::git(&["add", "--all", "--", "."])?;
+            Self::git(&["commit", "--quiet", "--author", &Self::author("rpublish"), "-m", "Import existing articles"])?;
+        }
+        log::info!("Article history enabled");
+        Ok(history)
+    }
+
+    // Commit the files of an article, nothing is committed if they didn't change
+    pub fn commit(&self, article_id: &str, editor: &str, message: &str) -> Result<()> {
+        if !is_safe_id(article_id) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid article id"));
+        }
+        let pathspec = Self::article_pathspec(article_id);
+        let _guard = self.lock.lock_or_recover();
+        if !self.has_changes(&[&pathspec])? {
+            return Ok(());
+        }
+        Self::git(&["add", "--all", "--", &pathspec])?;
+        Self::git(&["commit", "--quiet", "--author", &Self::author(editor), "-m", message, "--", &pathspec])?;
+        Ok(())
+    }
+
+    // Commits that changed the article, newest first
+    pub fn log(&self, article_id: &str) -> Result<Vec<HistoryEntry>> {
+        if !is_safe_id(article_id) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid article id"));
+        }
+        let output = Self::git(&[
+            "log", "--format=%H%x1f%an%x1f%aI%x1f%s", "--", &Self::article_pathspec(article_id)
+        ])?;
+        let entries = String::from_utf8_lossy(&output.stdout).lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\u{1f}');
+                Some(HistoryEntry {
+                    commit: fields.next()?.to_string(),
+                    author: fields.next()?.to_string(),
+                    date: fields.next()?.to_string(),
+                    message: fields.next()?.to_string()
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    // Push the current branch to the configured remote
+    pub fn push(&self) -> Result<()> {
+        let remote = match &self.remote {
+            Some(remote) => remote,
+            None => return Err(Error::new(ErrorKind::NotFound, "No git remote configured")),
+        };
+        let _guard = self.lock.lock_or_recover();
+        Self::git(&["push", "--quiet", remote, "HEAD"])?;
+        log::info!("Article history pushed to {}", remote);
+        Ok(())
+    }
+
+    fn has_changes(&self, pathspecs: &[&str]) -> Result<bool> {
+        let mut args = vec!["status", "--porcelain", "--"];
+        args.extend_from_slice(pathspecs);
+        Ok(!Self::git(&args)?.stdout.is_empty())
+    }
+
+    // Both versions of the article, matches deleted files still in the repository
+    fn article_pathspec(article_id: &str) -> String {
+        format!(":(glob)articles/*/{}.json", article_id)
+    }
+
+    fn author(editor: &str) -> String {
+        format!("{} <{}@rpublish>", editor, editor)
+    }
+
+    fn git(args: &[&str]) -> Result<Output> {
+        let output = Command::new("git")
+            .args(["-c", "user.name=rpublish", "-c", "user.email=rpublish@localhost"])
+            .args(args)
+            .current_dir(REPOSITORY_PATH)
+            .output()?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(Error::other(format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+}
@@ -1,12 +1,11 @@
-use std::fmt;
-use std::fs::{File};
-use std::io::{BufReader, Error, ErrorKind, Write, stdin, stdout};
-use std::path::Path;
+use std::{env, fmt, fs};
+use std::io::{ErrorKind, Write, stdin, stdout};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 use chrono::prelude::*;
 use rand_core::OsRng;
 use serde::{Serialize, Deserialize};
-use serde_json;
+use uuid::Uuid;
 use argon2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2
@@ -15,118 +14,210 @@ extern crate termion;
 use termion::input::TermRead;
 use termion::{color, style};
 
-use crate::helpers::write_json;
+use crate::locks::{MutexRecover, RwLockRecover};
+use super::storage::Storage;
+
+// One-time file with the first admin credentials, removed once the admin is created
+const SETUP_PATH: &str = "data/setup.json";
+
+#[derive(Deserialize)]
+struct SetupFile {
+    username: String,
+    password: String
+}
 
 pub struct IdentityManager
 {
-    pub users: Users,
-    pub sessions: Sessions
+    pub users: RwLock<Users>,
+    // Sessions are checked on every dashboard request, they handle their own locking
+    pub sessions: Sessions,
+    // One-time token of the browser setup wizard, only set while there are no users
+    setup_token: Mutex<Option<String>>
 }
 
 impl IdentityManager {
-    pub fn new() -> Self {
-        match Users::load_users() {
-            Ok(users) => {
-                match Sessions::load_sessions() {
-                    Ok(sessions) => {
-                        Self {
-                            users,
-                            sessions
-                        }
-                    },
-                    Err(_) => panic!("{}Failed loading sessions!", color::Fg(color::Red))
-                }
-            },
-            Err(_) => panic!("{}Failed loading users!", color::Fg(color::Red))
+    pub fn new(storage: Arc<dyn Storage>, setup_wizard: bool) -> Result<Self, std::io::Error> {
+        let users = Users::load_users(storage.clone(), setup_wizard)?;
+        let sessions = Sessions::load_sessions(storage)?;
+
+        let setup_token = if users.list().is_empty() {
+            let token = Uuid::new_v4().to_simple().to_string();
+            log::warn!("First run: open /auth/setup?token={} to create the admin user", token);
+            Some(token)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            users: RwLock::new(users),
+            sessions,
+            setup_token: Mutex::new(setup_token)
+        })
+    }
+
+    // A thread panicked while changing the users or sessions
+    pub fn is_poisoned(&self) -> bool {
+        self.users.is_poisoned() || self.sessions.is_poisoned() || self.setup_token.is_poisoned()
+    }
+
+    // Take every lock once so the poisoned ones are recovered without waiting for a request
+    pub fn recover(&self) {
+        drop(self.users.read_or_recover());
+        self.sessions.recover();
+        drop(self.setup_token.lock_or_recover());
+    }
+
+    pub fn setup_pending(&self) -> bool {
+        self.setup_token.lock_or_recover().is_some()
+    }
+
+    // Create the first admin with the token printed at startup, the token is only used once
+    pub fn complete_setup(&self, token: &str, username: &str, password: &str) -> Result<(), IdentityError> {
+        let mut setup_token = self.setup_token.lock_or_recover();
+        match setup_token.as_deref() {
+            Some(expected) if constant_time_eq(expected.as_bytes(), token.as_bytes()) => {},
+            _ => return Err(IdentityError{
+                kind: IdentityErrorKind::InvalidSetupToken
+            }),
         }
+        self.users.write_or_recover().create(username, password, &[UserPermissions::Admin, UserPermissions::Editor])?;
+        *setup_token = None;
+        log::info!("Admin user {} created from the setup wizard", username);
+        Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// Compare secrets without returning at the first different byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
 pub struct Sessions {
-    sessions: HashMap<String, Session>
+    sessions: RwLock<HashMap<String, Session>>,
+    // Saves write a snapshot taken while holding this lock so the last save has the latest sessions
+    save_lock: Mutex<()>,
+    storage: Arc<dyn Storage>
 }
 
 impl Sessions {
-    pub fn load_sessions() -> Result<Self, std::io::Error> {
-        let path = Path::new("data/auth/sessions.json");
-        match File::open(path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                match serde_json::from_reader::<BufReader<File>, Self>(reader) {
-                    Ok(users) => {
-                        println!("{}- Sessions loaded", color::Fg(color::Yellow));
-                        Ok(users)
-                    },
-                    Err(_) => {
-                        Err(Error::new(
-                            ErrorKind::InvalidData, 
-                            format!("{}Error deserializing file", color::Fg(color::Red))
-                        ))
-                    }
-                }
+    pub fn load_sessions(storage: Arc<dyn Storage>) -> Result<Self, std::io::Error> {
+        match storage.load_sessions() {
+            Ok(Some(sessions)) => {
+                log::info!("Sessions loaded");
+                Ok(Self {
+                    sessions: RwLock::new(sessions),
+                    save_lock: Mutex::new(()),
+                    storage
+                })
+            },
+            Ok(None) => {
+                log::info!("Sessions file not found, creating one");
+                let new_sessions = Self{
+                    sessions: RwLock::new(HashMap::new()),
+                    save_lock: Mutex::new(()),
+                    storage
+                };
+                new_sessions.save()?;
+                Ok(new_sessions)
             },
             Err(error) => {
-                match error.kind() {
-                    ErrorKind::NotFound => {
-                        println!("{}Sessions file Not Found, Creating one", color::Fg(color::Cyan));
-                        let new_sessions = Self{
-                            sessions: HashMap::new()
-                        };
-                        new_sessions.save();
-                        Ok(new_sessions)
-                    },
-                    ErrorKind::PermissionDenied => {
-                        println!("{}Error reading sessions file: Permision Denied", color::Fg(color::Red));
-                        Err(Error::new(ErrorKind::InvalidData, "File read error: Permision Denied"))
-                    },
-                    _ => {
-                        println!("{}Error reading sessions file {}", color::Fg(color::Red), error.to_string());
-                        Err(Error::new(ErrorKind::InvalidData, "File read error not expected"))
-                    }
-                }
+                log::error!("Error reading sessions {}", error);
+                Err(error)
             }
         }
     }
 
+    pub fn count(&self) -> usize {
+        self.sessions.read_or_recover().len()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.sessions.is_poisoned() || self.save_lock.is_poisoned()
+    }
+
+    fn recover(&self) {
+        drop(self.sessions.read_or_recover());
+        drop(self.save_lock.lock_or_recover());
+    }
+
     pub fn validate(&self, sessid: &str) -> bool {
-        self.sessions.get(sessid).is_some()
+        self.sessions.read_or_recover().contains_key(sessid)
     }
 
-    pub fn invalidate(&mut self, sessid: &str)
+    // Saving blocks, call it off the async threads. The session stays removed from memory
+    // when the save fails, it is only kept in the stored sessions
+    pub fn invalidate(&self, sessid: &str) -> Result<(), std::io::Error>
     {
-        self.sessions.remove(sessid);
-        self.save();
+        self.sessions.write_or_recover().remove(sessid);
+        self.save()
     }
 
-    pub fn create(&mut self, sessid: String, username: String, ip: String)
+    // Saving blocks, call it off the async threads. A session that can't be saved is removed
+    pub fn create(&self, sessid: String, username: String, ip: String) -> Result<(), std::io::Error>
     {
-        self.sessions.insert(sessid, Session{
+        self.sessions.write_or_recover().insert(sessid.to_owned(), Session{
             username,
             ip,
             date: chrono::offset::Utc::now(),
         });
-        self.save();
+        if let Err(error) = self.save() {
+            self.sessions.write_or_recover().remove(&sessid);
+            return Err(error);
+        }
+        Ok(())
     }
 
     pub fn get_user(&self, sessid: &str) -> Option<String> {
-        self.sessions.get(sessid).map(|session| session.username.to_owned())
+        self.sessions.read_or_recover().get(sessid).map(|session| session.username.to_owned())
     }
 
-    fn save(&self) {
-        match serde_json::to_string(self) {
-            Ok(json) => {
-                match write_json("data/auth/sessions.json", json) {
-                    Ok(_) => println!("{}Sessions file saved", color::Fg(color::Cyan)),
-                    Err(_) => println!("{}Failed to save sessions file", color::Fg(color::Red)),
-                }
+    // Sessions with their id, oldest first
+    pub fn list(&self) -> Vec<(String, Session)> {
+        let mut sessions: Vec<(String, Session)> = self.sessions.read_or_recover().iter()
+            .map(|(sessid, session)| (sessid.to_owned(), session.to_owned()))
+            .collect();
+        sessions.sort_by_key(|(_, session)| session.date);
+        sessions
+    }
+
+    // Log out every session of the user, returns how many were removed
+    pub fn invalidate_user(&self, username: &str) -> Result<usize, std::io::Error> {
+        let removed = {
+            let mut sessions = self.sessions.write_or_recover();
+            let count = sessions.len();
+            sessions.retain(|_, session| session.username != username);
+            count - sessions.len()
+        };
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn invalidate_all(&self) -> Result<usize, std::io::Error> {
+        let removed = std::mem::take(&mut *self.sessions.write_or_recover()).len();
+        self.save()?;
+        Ok(removed)
+    }
+
+    // Validation isn't blocked while the sessions are written
+    fn save(&self) -> Result<(), std::io::Error> {
+        let _guard = self.save_lock.lock_or_recover();
+        let sessions = self.sessions.read_or_recover().clone();
+        match self.storage.save_sessions(&sessions) {
+            Ok(_) => {
+                log::info!("Sessions file saved");
+                Ok(())
+            },
+            Err(error) => {
+                log::error!("Failed to save sessions file: {}", error);
+                Err(error)
             },
-            Err(_) => println!("{}Failed to serialize sessions file", color::Fg(color::Red))
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Session {
     username: String,
     ip: String,
@@ -134,76 +225,134 @@ pub struct Session {
 }
 
 impl Session {
-    
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    pub fn date(&self) -> &DateTime<Utc> {
+        &self.date
+    }
 }
 
-#[derive(Serialize, Deserialize)]
 pub struct Users
 {
-    users: Vec<User>
+    users: Vec<User>,
+    storage: Arc<dyn Storage>
 }
 
 impl Users {
-    pub fn load_users() -> Result<Self, std::io::Error> {
-        let path = Path::new("data/auth/users.json");
-        match File::open(path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                match serde_json::from_reader::<BufReader<File>, Self>(reader) {
-                    Ok(users) => {
-                        println!("{}- Users loaded", color::Fg(color::Yellow));
-                        Ok(users)
-                    },
-                    Err(_) => {
-                        Err(Error::new(
-                            ErrorKind::InvalidData, 
-                            format!("{}Error deserializing file", color::Fg(color::Red))
-                        ))
+    // Load the users without prompting, there are none if they were never saved
+    pub fn open(storage: Arc<dyn Storage>) -> Result<Self, std::io::Error> {
+        let users = storage.load_users()?.unwrap_or_default();
+        Ok(Self {
+            users,
+            storage
+        })
+    }
+
+    // The first admin is created when there are no users, see initial_admin
+    pub fn load_users(storage: Arc<dyn Storage>, setup_wizard: bool) -> Result<Self, std::io::Error> {
+        match storage.load_users() {
+            Ok(Some(users)) if !users.is_empty() => {
+                log::info!("Users loaded");
+                Ok(Self {
+                    users,
+                    storage
+                })
+            },
+            Ok(_) => {
+                log::info!("No users found, creating the admin user");
+                let mut new_users = Self{
+                    users: Vec::new(),
+                    storage
+                };
+
+                if let Some((user_name, user_password, source)) = Self::initial_admin(setup_wizard)? {
+                    new_users.create(&user_name, &user_password, &[UserPermissions::Admin, UserPermissions::Editor])
+                        .map_err(|error| std::io::Error::new(ErrorKind::InvalidInput, error.to_string()))?;
+                    log::info!("Admin user {} created from the {}", user_name, source);
+
+                    // The setup file holds a password, it is only used once
+                    if source == "setup file" {
+                        if let Err(error) = fs::remove_file(SETUP_PATH) {
+                            log::error!("Failed to remove {}, delete it by hand: {}", SETUP_PATH, error);
+                        }
                     }
                 }
+
+                Ok(new_users)
             },
             Err(error) => {
-                match error.kind() {
-                    ErrorKind::NotFound => {
-                        println!("{}Users file Not Found, Creating one", color::Fg(color::Cyan));
-                        let mut new_users = Self{
-                            users: Vec::new()
-                        };
-                        let user_name = Self::read_input("Admin username: ");
-
-                        loop {
-                            
-                            let user_password = Self::read_input_hidden("Admin password: ")
-                                .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
-                            let user_password_repeat = Self::read_input_hidden("Admin password (repeat): ")
-                                .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
-
-                            if user_password == user_password_repeat {
-                                let mut new_user = User::new(user_name.trim(), user_password.trim());
-                                new_user.set_permission(UserPermissions::Admin);
-                                new_user.set_permission(UserPermissions::Editor);
-                                new_users.users.push(new_user);
-                                break;
-                            }
-                        }
+                log::error!("Error reading users {}", error);
+                Err(error)
+            }
+        }
+    }
 
-                        new_users.save();
+    // Username, password and source of the first admin, taken from the environment, the setup
+    // file or the terminal in that order. None leaves the admin to the browser setup wizard,
+    // without any of them the server can't start
+    fn initial_admin(setup_wizard: bool) -> Result<Option<(String, String, &'static str)>, std::io::Error> {
+        if let Ok(user_name) = env::var("RPUBLISH_ADMIN_USER") {
+            let user_password = match (env::var("RPUBLISH_ADMIN_PASSWORD"), env::var("RPUBLISH_ADMIN_PASSWORD_FILE")) {
+                (Ok(password), _) => password,
+                (_, Ok(password_file)) => fs::read_to_string(password_file)?.trim_end_matches(['\r', '\n']).to_string(),
+                _ => return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "RPUBLISH_ADMIN_USER is set without RPUBLISH_ADMIN_PASSWORD or RPUBLISH_ADMIN_PASSWORD_FILE"
+                )),
+            };
+            return Self::checked_admin(user_name, user_password, "environment");
+        }
 
-                        println!("{}{}User saved", color::Fg(color::Reset), style::Reset);
+        match fs::read_to_string(SETUP_PATH) {
+            Ok(setup) => {
+                let setup = serde_json::from_str::<SetupFile>(&setup).map_err(|error| std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to parse {}: {}", SETUP_PATH, error)
+                ))?;
+                return Self::checked_admin(setup.username, setup.password, "setup file");
+            },
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+            Err(_) => {},
+        }
 
-                        Ok(new_users)
-                    },
-                    ErrorKind::PermissionDenied => {
-                        println!("{}Error reading users file: Permision Denied", color::Fg(color::Red));
-                        Err(Error::new(ErrorKind::InvalidData, "File read error: Permision Denied"))
-                    },
-                    _ => {
-                        println!("{}Error reading users file {}", color::Fg(color::Red), error.to_string());
-                        Err(Error::new(ErrorKind::InvalidData, "File read error not expected"))
-                    }
+        if termion::is_tty(&stdin()) {
+            let user_name = Self::read_input("Admin username: ");
+            loop {
+                let user_password = Self::read_input_hidden("Admin password: ")
+                    .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+                let user_password_repeat = Self::read_input_hidden("Admin password (repeat): ")
+                    .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+
+                if user_password == user_password_repeat {
+                    return Ok(Some((user_name, user_password, "terminal")));
                 }
             }
         }
+
+        if setup_wizard {
+            return Ok(None);
+        }
+        Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "No admin user and no terminal to create one. Set RPUBLISH_ADMIN_USER and RPUBLISH_ADMIN_PASSWORD, \
+                write {} or enable the setup wizard with RPUBLISH_SETUP_WIZARD=1",
+                SETUP_PATH
+            )
+        ))
+    }
+
+    fn checked_admin(user_name: String, user_password: String, source: &'static str) -> Result<Option<(String, String, &'static str)>, std::io::Error> {
+        if user_name.trim().is_empty() || user_password.trim().is_empty() {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("Empty admin username or password in the {}", source)));
+        }
+        Ok(Some((user_name, user_password, source)))
     }
 
     fn read_input(text: &str) -> String {
@@ -214,25 +363,40 @@ impl Users {
         data.trim().to_string()
     }
 
-    fn read_input_hidden(text: &str) -> Option<String> {
+    pub fn read_input_hidden(text: &str) -> Option<String> {
         let mut stdout = stdout();
         print!("\n    {}{}{}{}{}", style::Reset, style::Bold, color::Fg(color::Yellow), text, color::Fg(color::Reset));
         stdout.flush().expect("Could not flush stdout");
         stdin().read_passwd(&mut stdout).expect("Error: unable to read user input")
     }
 
-    fn save(&self) {
-        match serde_json::to_string(self) {
-            Ok(json) => {
-                match write_json("data/auth/users.json", json) {
-                    Ok(_) => println!("{}Users file saved", color::Fg(color::Cyan)),
-                    Err(_) => println!("{}Failed to save users file", color::Fg(color::Red)),
-                }
+    // Changes are saved before they are kept, a failed save leaves the users as they were
+    fn save(&mut self, users: Vec<User>) -> Result<(), std::io::Error> {
+        match self.storage.save_users(&users) {
+            Ok(_) => {
+                log::info!("Users file saved");
+                self.users = users;
+                Ok(())
+            },
+            Err(error) => {
+                log::error!("Failed to save users file: {}", error);
+                Err(error)
             },
-            Err(_) => println!("{}Failed to serialize users file", color::Fg(color::Red))
         }
     }
 
+    fn change(&mut self, username: &str, change: impl FnOnce(&mut User)) -> Result<(), IdentityError> {
+        let mut users = self.users.clone();
+        let user = users.iter_mut()
+            .find(|user| user.user_name == username)
+            .ok_or(IdentityError{
+                kind: IdentityErrorKind::UserNotFound
+            })?;
+        change(user);
+        self.save(users)?;
+        Ok(())
+    }
+
     pub fn get(&self, username: &str) -> Result<&User, IdentityError> {
         let user_iter = self.users.iter();
         for user in user_iter {
@@ -244,9 +408,12 @@ impl Users {
             kind: IdentityErrorKind::UserNotFound
         })
     }
-    
-    #[allow(dead_code)]
-    pub fn create(&mut self, username: &str, password: &str) -> Result<&User, IdentityError> {
+
+    pub fn list(&self) -> &[User] {
+        &self.users
+    }
+
+    pub fn create(&mut self, username: &str, password: &str, permissions: &[UserPermissions]) -> Result<&User, IdentityError> {
         match self.get(username) {
             Ok(_) => {
                 Err(IdentityError{
@@ -254,41 +421,65 @@ impl Users {
                 })
             },
             Err(_) => {
-                let user = User::new(username.trim(), password.trim());
-                self.users.push(user);
-                self.save();
+                let mut user = User::new(username.trim(), password.trim());
+                for permission in permissions {
+                    user.set_permission(*permission);
+                }
+                let mut users = self.users.clone();
+                users.push(user);
+                self.save(users)?;
                 Ok(self.users.last().unwrap())
             },
         }
     }
 
-    #[allow(dead_code)]
-    pub fn delete(&mut self, username: &str, password: &str) -> Result<(), IdentityError> {
-        match self.get(username) {
-            Ok(user) => {
-                match user.authenticate(password) {
-                    Ok(_) => todo!(),
-                    Err(_) => todo!(),
-                }
-            },
-            Err(_) => {
-                Err(IdentityError{
-                    kind: IdentityErrorKind::UserNotFound
-                })
-            },
-        }
+    // Disabled users can't log in, their sessions are removed by the caller
+    pub fn set_enabled(&mut self, username: &str, enabled: bool) -> Result<(), IdentityError> {
+        self.change(username, |user| user.enabled = enabled)
+    }
+
+    pub fn set_password(&mut self, username: &str, password: &str) -> Result<(), IdentityError> {
+        let password_hash = User::hash_password(password);
+        self.change(username, |user| {
+            user.password_hash = password_hash;
+            user.password_update_date = chrono::offset::Utc::now();
+        })
+    }
+
+    pub fn grant(&mut self, username: &str, permission: UserPermissions) -> Result<(), IdentityError> {
+        self.change(username, |user| user.set_permission(permission))
+    }
+
+    pub fn revoke(&mut self, username: &str, permission: UserPermissions) -> Result<(), IdentityError> {
+        self.change(username, |user| user.permissions.retain(|granted| *granted != permission))
     }
 }
 
-#[allow(dead_code)]
-#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum UserPermissions
 {
     Admin,
     Editor
 }
 
-#[derive(Serialize, Deserialize)]
+impl UserPermissions {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "admin" => Some(UserPermissions::Admin),
+            "editor" => Some(UserPermissions::Editor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UserPermissions::Admin => "admin",
+            UserPermissions::Editor => "editor",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct User
 {
     user_name: String,
@@ -322,8 +513,38 @@ impl User {
         ).unwrap().to_string()
     }
 
+    pub fn user_name(&self) -> &str {
+        &self.user_name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn permissions(&self) -> &[UserPermissions] {
+        &self.permissions
+    }
+
+    pub fn created_date(&self) -> &DateTime<Utc> {
+        &self.created_date
+    }
+
     pub fn authenticate(&self, password: &str) -> Result<(), IdentityError> {
-        let parsed_hash = PasswordHash::new(&self.password_hash).unwrap();
+        if !self.enabled {
+            return Err(IdentityError{
+                kind: IdentityErrorKind::UserDisabled
+            });
+        }
+        // A hash edited by hand or cut by a crash can't be parsed, the login fails
+        let parsed_hash = match PasswordHash::new(&self.password_hash) {
+            Ok(parsed_hash) => parsed_hash,
+            Err(error) => {
+                log::error!("Unreadable password hash of user {}: {}", self.user_name, error);
+                return Err(IdentityError{
+                    kind: IdentityErrorKind::AuthFailed
+                });
+            },
+        };
         match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
             Ok(_) => Ok(()),
             Err(_) => {
@@ -341,22 +562,50 @@ impl User {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum IdentityErrorKind
 {
     UserAlreadyExist,
     UserNotFound,
-    AuthFailed
+    UserDisabled,
+    AuthFailed,
+    InvalidSetupToken,
+    // Saving the users or sessions failed
+    Storage(std::io::Error)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct IdentityError {
     kind: IdentityErrorKind
 }
 
+impl IdentityError {
+    pub fn kind(&self) -> &IdentityErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> IdentityErrorKind {
+        self.kind
+    }
+}
+
+impl From<std::io::Error> for IdentityError {
+    fn from(error: std::io::Error) -> Self {
+        Self { kind: IdentityErrorKind::Storage(error) }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
 impl fmt::Display for IdentityError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Indentity Error")
+        match &self.kind {
+            IdentityErrorKind::UserAlreadyExist => write!(f, "User already exists"),
+            IdentityErrorKind::UserNotFound => write!(f, "User not found"),
+            IdentityErrorKind::UserDisabled => write!(f, "User is disabled"),
+            IdentityErrorKind::AuthFailed => write!(f, "Authentication failed"),
+            IdentityErrorKind::InvalidSetupToken => write!(f, "Invalid setup token"),
+            IdentityErrorKind::Storage(error) => write!(f, "Saving the users failed: {}", error),
+        }
     }
 }
\ No newline at end of file
@@ -1,81 +1,34 @@
-use std::{collections::HashMap, fs};
+use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
-
-use crate::{helpers::write_json};
 
 use super::articles_manager::article::Article;
+use super::editorjs::EditorDocument;
+use super::storage::Storage;
+
+// Bump when the metadata fields change so cached entries are rebuilt
+const METADATA_VERSION: u32 = 2;
+
+// Max characters of the excerpt derived from the article paragraphs
+const EXCERPT_LENGTH: usize = 200;
 
+// In memory metadata, entries are saved to the storage by the articles manager
 pub struct MetadataCache{
-    pub path: PathBuf,
     pub articles: HashMap<String, ArticleMetadata>
 }
 
 impl MetadataCache {
-    pub fn new(relative_path: &str) -> MetadataCache {
-        let mut path = PathBuf::new();
-        path.push("data/cache/metadata");
-        path.push(relative_path);
-        Self::load_from_disk(path)
+    pub fn new(name: &str, storage: &dyn Storage) -> MetadataCache {
+        Self::load_from_disk(name, storage)
     }
 
-    // Load existing cache files
-    fn load_from_disk(path: PathBuf) -> MetadataCache {
-        match fs::read_dir(&path) {
-            Ok(metadata_files) => {
-                let mut cache = Self {
-                    path,
-                    articles: HashMap::new()
-                };
-                for file in metadata_files {
-                    let file_path = file.unwrap().path();
-                    if let Some(extension) = file_path.extension() {
-                        if extension == "json" {
-                            let article_id = String::from(file_path.file_stem().unwrap().to_str().unwrap());
-                            match fs::read_to_string(&file_path) {
-                                Ok(metadata_string) => {
-                                    match serde_json::from_str::<ArticleMetadata>(metadata_string.as_str()) {
-                                        Ok(article_metadata) => {
-                                            cache.articles.insert(article_id, article_metadata);
-                                        },
-                                        Err(_) => {
-                                            println!("Failed to parse {}.json", article_id);
-                                        }
-                                    }
-                                },
-                                Err(_) => println!("Cannot read {} as string", file_path.to_str().unwrap()),
-                            }
-                        }
-                    }
-                }
-                cache
-            },
-            Err(_) => {
-                Self {
-                    path,
-                    articles: HashMap::new()
-                }
-            },
-        }
-    }
-
-    // Save cache to disk
-    pub fn save_to_disk (&self) {
-        for (article_id, article_metadata) in &self.articles {
-            match serde_json::to_string(article_metadata) {
-                Ok(json) => {
-                    let mut metadata_path = self.path.to_owned();
-                    metadata_path.push(format!("{}.json", &article_id));
-                    match write_json(metadata_path.to_str().unwrap(), json) {
-                        Ok(_) => {
-                            // println!("Metadata cache of {} saved", &article_id)
-                        },
-                        Err(_) => println!("Failed to save metadata of {}", &article_id),
-                    }
-                },
-                Err(_) => println!("Failed to serialize metadata of {}", &article_id)
-            }
+    // Load existing cache entries
+    fn load_from_disk(name: &str, storage: &dyn Storage) -> MetadataCache {
+        let mut articles = storage.load_metadata(name);
+        // Outdated entries are left out so they are rebuilt from the article
+        articles.retain(|_, article_metadata| article_metadata.version == METADATA_VERSION);
+        Self {
+            articles
         }
     }
 
@@ -91,45 +44,57 @@ impl MetadataCache {
         self.articles.get(article_id)
     }
 
-    // Add or update article metadata
-    pub fn set_metadata(&mut self, article_id: &str, article: &Article) {
-        if self.articles.contains_key(article_id) {
-            match self.articles.get_mut(article_id) {
-                Some(metadata) => {
-                    metadata.title = article.title.to_owned();
-                    metadata.author = article.author.to_owned();
-                    metadata.tags = article.tags.to_owned();
-                    metadata.update_date = article.update_date.to_owned();
-                    self.save_to_disk();
-                }
-                None => {
-                    println!("Cannot get a mutable ref to article metadata");
-                },
-            }
-        } else {
-            self.articles.insert(article_id.to_string(), ArticleMetadata {
-                title: article.title.to_owned(),
-                author: article.author.to_owned(),
-                tags: article.tags.to_owned(),
-                created_date: article.created_date.to_owned(),
-                update_date: article.update_date.to_owned()
-            });
-            self.save_to_disk();
-        }
+    // Add or update article metadata, the caller saves the entry to the storage
+    pub fn set_metadata(&mut self, article_id: &str, metadata: ArticleMetadata) {
+        self.articles.insert(article_id.to_string(), metadata);
     }
 
     pub fn remove_metadata(&mut self, article_id: &str) {
-        if self.articles.contains_key(article_id) {
-            self.articles.remove(article_id);
-        }
+        self.articles.remove(article_id);
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ArticleMetadata {
+    #[serde(default)]
+    pub version: u32,
     pub title: String,
     pub author: String,
     pub tags: Vec<String>,
     pub created_date: DateTime<Utc>,
-    pub update_date: DateTime<Utc>
+    pub update_date: DateTime<Utc>,
+    pub excerpt: String,
+    pub cover_image: Option<String>,
+    pub word_count: usize,
+    // Estimated minutes to read the article
+    pub reading_time: usize,
+    // Left out of the sitemap
+    #[serde(default)]
+    pub noindex: bool
+}
+
+impl ArticleMetadata {
+    pub fn from_article(article: &Article) -> Self {
+        Self::from_document(article, &EditorDocument::parse_lossy(&article.data))
+    }
+
+    // With the data already parsed into document
+    pub fn from_document(article: &Article, document: &EditorDocument) -> Self {
+        Self {
+            version: METADATA_VERSION,
+            title: article.title.to_owned(),
+            author: article.author.to_owned(),
+            tags: article.tags.to_owned(),
+            created_date: article.created_date.to_owned(),
+            update_date: article.update_date.to_owned(),
+            excerpt: match &article.excerpt {
+                Some(excerpt) => excerpt.to_owned(),
+                None => document.excerpt(EXCERPT_LENGTH),
+            },
+            cover_image: article.cover_image.to_owned().or_else(|| document.first_image()),
+            word_count: document.word_count(),
+            reading_time: document.reading_time(),
+            noindex: article.noindex
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::Utc;
+
+use crate::locks::MutexRecover;
+use super::RPublishApp;
+use super::overview;
+
+// Upper bounds of the request latency histogram, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Latency {
+    // Requests at or under every bucket bound, not cumulative until rendered
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64
+}
+
+#[derive(Default)]
+struct RequestMetrics {
+    // By method, route and status
+    counts: BTreeMap<(String, String, u16), u64>,
+    // By method and route
+    latencies: BTreeMap<(String, String), Latency>
+}
+
+// Counters exposed in the Prometheus text format at /metrics. Routes are the matched
+// patterns like "/article/{article_id}" so the number of series stays bounded
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<RequestMetrics>,
+    failed_logins: AtomicU64
+}
+
+// Label values are quoted, backslashes, quotes and line breaks escaped
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut requests = self.requests.lock_or_recover();
+        *requests.counts.entry((method.to_string(), route.to_string(), status)).or_insert(0) += 1;
+        let latency = requests.latencies.entry((method.to_string(), route.to_string())).or_default();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            latency.buckets[bucket] += 1;
+        }
+        latency.sum += seconds;
+        latency.count += 1;
+    }
+
+    pub fn record_failed_login(&self) {
+        self.failed_logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render_requests(&self, out: &mut String) {
+        let requests = self.requests.lock_or_recover();
+        out.push_str("# HELP rpublish_http_requests_total Requests answered by route and status\n");
+        out.push_str("# TYPE rpublish_http_requests_total counter\n");
+        for ((method, route, status), count) in &requests.counts {
+            let _ = writeln!(
+                out, "rpublish_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                label(method), label(route), status, count
+            );
+        }
+
+        out.push_str("# HELP rpublish_http_request_duration_seconds Time to answer requests by route\n");
+        out.push_str("# TYPE rpublish_http_request_duration_seconds histogram\n");
+        for ((method, route), latency) in &requests.latencies {
+            let labels = format!("method=\"{}\",route=\"{}\"", label(method), label(route));
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(latency.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(out, "rpublish_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative);
+            }
+            let _ = writeln!(out, "rpublish_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, latency.count);
+            let _ = writeln!(out, "rpublish_http_request_duration_seconds_sum{{{}}} {}", labels, latency.sum);
+            let _ = writeln!(out, "rpublish_http_request_duration_seconds_count{{{}}} {}", labels, latency.count);
+        }
+    }
+}
+
+// Metric with a single value and no labels
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+}
+
+// Every metric of the server, the article counts walk the index
+pub fn render(app: &RPublishApp) -> String {
+    let mut out = String::new();
+    app.metrics.render_requests(&mut out);
+
+    let articles = overview::article_counts(app);
+    out.push_str("# HELP rpublish_articles Articles by status\n# TYPE rpublish_articles gauge\n");
+    for (status, count) in [
+        ("draft", articles.drafts),
+        ("published", articles.published),
+        ("changed", articles.changed),
+        ("trashed", articles.trashed),
+    ] {
+        let _ = writeln!(out, "rpublish_articles{{status=\"{}\"}} {}", status, count);
+    }
+
+    metric(&mut out, "rpublish_sessions", "Open dashboard sessions", "gauge", app.identity_manager.sessions.count());
+
+    let cache = app.articles_manager.cache_stats();
+    let lookups = cache.hits + cache.misses;
+    metric(&mut out, "rpublish_articles_cache_hits_total", "Articles read from the cache", "counter", cache.hits);
+    metric(&mut out, "rpublish_articles_cache_misses_total", "Articles read from the storage", "counter", cache.misses);
+    metric(
+        &mut out, "rpublish_articles_cache_hit_ratio", "Hits of every article read since the start", "gauge",
+        if lookups == 0 { 0.0 } else { cache.hits as f64 / lookups as f64 }
+    );
+    metric(&mut out, "rpublish_articles_cache_entries", "Articles in the cache", "gauge", cache.entries);
+    metric(&mut out, "rpublish_articles_cache_size_bytes", "Memory used by the cache", "gauge", cache.size);
+    metric(&mut out, "rpublish_articles_cache_budget_bytes", "Memory the cache can use", "gauge", cache.budget);
+
+    metric(
+        &mut out, "rpublish_failed_logins_total", "Failed dashboard logins since the start", "counter",
+        app.metrics.failed_logins.load(Ordering::Relaxed)
+    );
+    metric(&mut out, "rpublish_uptime_seconds", "Time since the server started", "gauge", (Utc::now() - app.started).num_seconds());
+    let _ = writeln!(
+        out, "# HELP rpublish_build_info Version of the server\n# TYPE rpublish_build_info gauge\nrpublish_build_info{{version=\"{}\"}} 1",
+        env!("CARGO_PKG_VERSION")
+    );
+    out
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::RPublishApp;
+use super::articles_manager::listing::{ArticleFilter, ListingSort, ListingStatus, SortField, SortOrder};
+use super::audit::{AuditAction, AuditFilter};
+
+// Entries listed in the recent edits and logins
+const RECENT_COUNT: usize = 10;
+
+// Period of the edits counted by user
+const EDITS_DAYS: i64 = 30;
+
+// No scheduled count, articles have no publish date and only go public when an editor publishes them
+#[derive(Serialize)]
+pub struct ArticleCounts {
+    // Drafts never published
+    pub drafts: usize,
+    pub published: usize,
+    // Published articles with unpublished changes
+    pub changed: usize,
+    pub trashed: usize
+}
+
+#[derive(Serialize)]
+pub struct RecentEdit {
+    pub date: DateTime<Utc>,
+    pub editor: String,
+    pub action: String,
+    pub article_id: String,
+    pub title: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct RecentLogin {
+    pub date: DateTime<Utc>,
+    pub username: String,
+    pub ip: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct DiskUsage {
+    // Everything in data/, uploads included
+    pub data_bytes: u64,
+    pub uploads_bytes: u64
+}
+
+#[derive(Serialize)]
+pub struct Overview {
+    pub articles: ArticleCounts,
+    pub recent_edits: Vec<RecentEdit>,
+    // Article changes of the last days by editor
+    pub edits_by_user: BTreeMap<String, usize>,
+    // Only for admins, None otherwise
+    pub recent_logins: Option<Vec<RecentLogin>>,
+    pub failed_logins_24h: Option<usize>,
+    pub disk: DiskUsage,
+    pub started: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    pub version: &'static str
+}
+
+fn is_article_action(action: AuditAction) -> bool {
+    matches!(
+        action,
+        AuditAction::ArticleCreate | AuditAction::ArticleUpdate | AuditAction::ArticlePublish
+            | AuditAction::ArticleUnpublish | AuditAction::ArticleDiscard | AuditAction::ArticleDelete
+            | AuditAction::ArticleRestore
+    )
+}
+
+fn dir_size(path: &Path) -> u64 {
+    crate::themes::walk_files(path, None).iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn count(app: &RPublishApp, status: ListingStatus) -> usize {
+    let filter = ArticleFilter { status: Some(status), ..ArticleFilter::default() };
+    let sort = ListingSort { field: SortField::UpdateDate, order: SortOrder::Desc };
+    app.articles_manager.index().query_articles(&filter, 0, 0, sort, None).total
+}
+
+pub fn article_counts(app: &RPublishApp) -> ArticleCounts {
+    ArticleCounts {
+        drafts: count(app, ListingStatus::DraftOnly),
+        published: count(app, ListingStatus::Published),
+        changed: count(app, ListingStatus::Changed),
+        trashed: app.articles_manager.list_trashed().len()
+    }
+}
+
+// Summary of the site for the dashboard home, reads the audit log and walks data/
+pub fn overview(app: &RPublishApp, admin: bool) -> Overview {
+    let articles = article_counts(app);
+
+    let now = Utc::now();
+    let filter = AuditFilter { since: Some(now - Duration::days(EDITS_DAYS)), ..AuditFilter::default() };
+    let entries = app.audit.query(&filter).unwrap_or_else(|error| {
+        log::error!("Failed to read the audit log: {}", error);
+        Vec::new()
+    });
+
+    let mut edits_by_user: BTreeMap<String, usize> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| is_article_action(entry.action)) {
+        *edits_by_user.entry(entry.actor.to_owned()).or_insert(0) += 1;
+    }
+    let recent_edits = {
+        let index = app.articles_manager.index();
+        entries.iter()
+            .filter(|entry| is_article_action(entry.action))
+            .take(RECENT_COUNT)
+            .map(|entry| {
+                let article_id = entry.target.to_owned().unwrap_or_default();
+                RecentEdit {
+                    date: entry.date,
+                    editor: entry.actor.to_owned(),
+                    action: entry.action.name(),
+                    title: index.latest_metadata(&article_id).map(|metadata| metadata.title.to_owned()),
+                    article_id
+                }
+            })
+            .collect()
+    };
+
+    // Logins and their addresses are only shown to admins
+    let (recent_logins, failed_logins_24h) = if admin {
+        let recent_logins = entries.iter()
+            .filter(|entry| entry.action == AuditAction::Login)
+            .take(RECENT_COUNT)
+            .map(|entry| RecentLogin {
+                date: entry.date,
+                username: entry.actor.to_owned(),
+                ip: entry.ip.to_owned()
+            })
+            .collect();
+        let failed = entries.iter()
+            .filter(|entry| entry.action == AuditAction::LoginFailed && entry.date > now - Duration::hours(24))
+            .count();
+        (Some(recent_logins), Some(failed))
+    } else {
+        (None, None)
+    };
+
+    Overview {
+        articles,
+        recent_edits,
+        edits_by_user,
+        recent_logins,
+        failed_logins_24h,
+        disk: DiskUsage {
+            data_bytes: dir_size(Path::new("data")),
+            uploads_bytes: dir_size(Path::new("data/public"))
+        },
+        started: app.started,
+        uptime_seconds: (now - app.started).num_seconds(),
+        version: env!("CARGO_PKG_VERSION")
+    }
+}
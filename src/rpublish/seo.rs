@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use super::articles_manager::article::Article;
+use super::editorjs::escape_html;
+use super::metadata_cache::ArticleMetadata;
+
+// Search engine and social sharing information of a published article
+pub struct ArticleSeo {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub image: Option<String>,
+    pub author: String,
+    // og:site_name, left out when None
+    pub site_name: Option<String>,
+    pub noindex: bool,
+    pub published_date: DateTime<Utc>,
+    pub modified_date: DateTime<Utc>
+}
+
+impl ArticleSeo {
+    // Use the article overrides, falling back to the title, excerpt and cover image of its metadata
+    pub fn new(article_id: &str, article: &Article, metadata: &ArticleMetadata, base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        Self {
+            title: article.seo_title.to_owned().unwrap_or_else(|| metadata.title.to_owned()),
+            description: article.seo_description.to_owned().unwrap_or_else(|| metadata.excerpt.to_owned()),
+            canonical_url: format!("{}/article/{}", base_url, article_id),
+            image: article.social_image.to_owned()
+                .or_else(|| metadata.cover_image.to_owned())
+                .map(|image| absolute_url(base_url, &image)),
+            author: metadata.author.to_owned(),
+            site_name: None,
+            noindex: article.noindex,
+            published_date: metadata.created_date,
+            modified_date: metadata.update_date
+        }
+    }
+
+    // Meta tags to place in the page head, the title tag included
+    pub fn render_head(&self) -> String {
+        let mut tags: Vec<String> = vec![
+            format!("<title>{}</title>", escape_html(&self.title)),
+            meta_name("description", &self.description),
+            format!("<link rel=\"canonical\" href=\"{}\">", escape_html(&self.canonical_url)),
+        ];
+        if self.noindex {
+            tags.push(meta_name("robots", "noindex, nofollow"));
+        }
+
+        // Open Graph
+        tags.push(meta_property("og:type", "article"));
+        if let Some(site_name) = &self.site_name {
+            tags.push(meta_property("og:site_name", site_name));
+        }
+        tags.push(meta_property("og:title", &self.title));
+        tags.push(meta_property("og:description", &self.description));
+        tags.push(meta_property("og:url", &self.canonical_url));
+        tags.push(meta_property("article:published_time", &self.published_date.to_rfc3339()));
+        tags.push(meta_property("article:modified_time", &self.modified_date.to_rfc3339()));
+        tags.push(meta_property("article:author", &self.author));
+
+        // Twitter card, large when there is an image to show
+        tags.push(meta_name("twitter:card", if self.image.is_some() {"summary_large_image"} else {"summary"}));
+        tags.push(meta_name("twitter:title", &self.title));
+        tags.push(meta_name("twitter:description", &self.description));
+
+        if let Some(image) = &self.image {
+            tags.push(meta_property("og:image", image));
+            tags.push(meta_name("twitter:image", image));
+        }
+
+        tags.push(format!("<script type=\"application/ld+json\">{}</script>", self.json_ld()));
+        tags.join("\n        ")
+    }
+
+    // BlogPosting structured data
+    pub fn json_ld(&self) -> String {
+        let mut data = json!({
+            "@context": "https://schema.org",
+            "@type": "BlogPosting",
+            "headline": self.title,
+            "description": self.description,
+            "author": {
+                "@type": "Person",
+                "name": self.author
+            },
+            "datePublished": self.published_date.to_rfc3339(),
+            "dateModified": self.modified_date.to_rfc3339(),
+            "mainEntityOfPage": {
+                "@type": "WebPage",
+                "@id": self.canonical_url
+            }
+        });
+        if let Some(image) = &self.image {
+            data["image"] = json!(image);
+        }
+        // Keep the json from closing the script tag
+        data.to_string().replace("</", "<\\/")
+    }
+}
+
+fn meta_name(name: &str, content: &str) -> String {
+    format!("<meta name=\"{}\" content=\"{}\">", name, escape_html(content))
+}
+
+fn meta_property(property: &str, content: &str) -> String {
+    format!("<meta property=\"{}\" content=\"{}\">", property, escape_html(content))
+}
+
+// Social networks need absolute urls for images
+fn absolute_url(base_url: &str, url: &str) -> String {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        url.to_string()
+    } else {
+        format!("{}/{}", base_url, url.trim_start_matches('/'))
+    }
+}
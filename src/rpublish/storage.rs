@@ -0,0 +1,163 @@
+pub mod filesystem;
+pub mod sqlite;
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+
+use super::articles_manager::ArticleStatus;
+use super::articles_manager::article::Article;
+use super::identity_manager::{Session, User};
+use super::metadata_cache::ArticleMetadata;
+
+use self::filesystem::FilesystemStorage;
+use self::sqlite::SqliteStorage;
+
+// Article documents, an article can have a draft and a published version at the same time
+pub trait ArticleStore {
+    fn list_articles(&self, status: ArticleStatus) -> Vec<String>;
+    fn read_article(&self, article_id: &str, status: ArticleStatus) -> Option<Article>;
+    fn write_article(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()>;
+    fn delete_article(&self, article_id: &str, status: ArticleStatus) -> Result<()>;
+    // Move the article from origin to target in a single step, replacing the target version
+    fn move_article(&self, article_id: &str, origin: ArticleStatus, target: ArticleStatus) -> Result<()>;
+    // Deleted articles are kept in the trash until restored, a newer deletion replaces them
+    fn list_trashed(&self, status: ArticleStatus) -> Vec<String>;
+    fn trash_article(&self, article_id: &str, status: ArticleStatus) -> Result<()>;
+    // Fails with AlreadyExists when the article is stored again with the same id
+    fn restore_article(&self, article_id: &str, status: ArticleStatus) -> Result<()>;
+    // Trashed versions as they are, for the migration between backends
+    fn read_trashed(&self, article_id: &str, status: ArticleStatus) -> Option<Article>;
+    fn write_trashed(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()>;
+    fn delete_trashed(&self, article_id: &str, status: ArticleStatus) -> Result<()>;
+}
+
+// Metadata caches, named by the article status they describe
+pub trait MetadataStore {
+    fn load_metadata(&self, cache_name: &str) -> HashMap<String, ArticleMetadata>;
+    fn save_metadata(&self, cache_name: &str, article_id: &str, metadata: &ArticleMetadata) -> Result<()>;
+    fn remove_metadata(&self, cache_name: &str, article_id: &str) -> Result<()>;
+}
+
+// None is returned when the users were never saved
+pub trait UserStore {
+    fn load_users(&self) -> Result<Option<Vec<User>>>;
+    fn save_users(&self, users: &[User]) -> Result<()>;
+}
+
+pub trait SessionStore {
+    fn load_sessions(&self) -> Result<Option<HashMap<String, Session>>>;
+    fn save_sessions(&self, sessions: &HashMap<String, Session>) -> Result<()>;
+}
+
+pub trait Storage: ArticleStore + MetadataStore + UserStore + SessionStore + Send + Sync {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend
+{
+    // Json files in the data directory
+    #[default]
+    Filesystem,
+    // Single sqlite database file
+    Sqlite,
+}
+
+impl StorageBackend {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "filesystem" => Some(StorageBackend::Filesystem),
+            "sqlite" => Some(StorageBackend::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn open(&self, sqlite_path: &str) -> Result<Arc<dyn Storage>> {
+        match self {
+            StorageBackend::Filesystem => Ok(Arc::new(FilesystemStorage::new())),
+            StorageBackend::Sqlite => Ok(Arc::new(SqliteStorage::open(sqlite_path)?)),
+        }
+    }
+
+    // Storage of the server, the interrupted moves of the filesystem storage are finished first.
+    // The command line opens it without them
+    pub fn open_recovered(&self, sqlite_path: &str) -> Result<Arc<dyn Storage>> {
+        if *self == StorageBackend::Filesystem {
+            FilesystemStorage::recover();
+        }
+        self.open(sqlite_path)
+    }
+}
+
+// Ids are used as file names and paths, keep them inside their directory
+pub fn is_safe_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub const METADATA_CACHES: [&str; 2] = ["draft", "published"];
+
+// Copy every article, trashed article, metadata entry, user and session from one storage into another,
+// entries only found in the target are removed so both end up with the same data
+pub fn migrate(origin: &dyn Storage, target: &dyn Storage) -> Result<()> {
+    log::info!("Migrating {} storage into {} storage", origin.name(), target.name());
+
+    for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+        let article_ids = origin.list_articles(status);
+        for article_id in &article_ids {
+            match origin.read_article(article_id, status) {
+                Some(article) => target.write_article(article_id, &article, status)?,
+                None => log::error!("Failed to read {} article {}, skipped", status.dir_name(), article_id),
+            }
+        }
+        for article_id in target.list_articles(status) {
+            if !article_ids.contains(&article_id) {
+                target.delete_article(&article_id, status)?;
+            }
+        }
+        log::info!("{} {} articles", article_ids.len(), status.dir_name());
+
+        // Kept so they can still be restored after the migration
+        let trashed_ids = origin.list_trashed(status);
+        for article_id in &trashed_ids {
+            match origin.read_trashed(article_id, status) {
+                Some(article) => target.write_trashed(article_id, &article, status)?,
+                None => log::error!("Failed to read trashed {} article {}, skipped", status.dir_name(), article_id),
+            }
+        }
+        for article_id in target.list_trashed(status) {
+            if !trashed_ids.contains(&article_id) {
+                target.delete_trashed(&article_id, status)?;
+            }
+        }
+        log::info!("{} trashed {} articles", trashed_ids.len(), status.dir_name());
+    }
+
+    for cache_name in METADATA_CACHES {
+        let metadata = origin.load_metadata(cache_name);
+        for (article_id, article_metadata) in &metadata {
+            target.save_metadata(cache_name, article_id, article_metadata)?;
+        }
+        for article_id in target.load_metadata(cache_name).keys() {
+            if !metadata.contains_key(article_id) {
+                target.remove_metadata(cache_name, article_id)?;
+            }
+        }
+        log::info!("{} {} metadata entries", metadata.len(), cache_name);
+    }
+
+    if let Some(users) = origin.load_users()? {
+        target.save_users(&users)?;
+        log::info!("{} users", users.len());
+    }
+
+    if let Some(sessions) = origin.load_sessions()? {
+        target.save_sessions(&sessions)?;
+        log::info!("{} sessions", sessions.len());
+    }
+
+    log::info!("Migration finished");
+    Ok(())
+}
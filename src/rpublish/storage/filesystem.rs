@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Result};
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use crate::helpers::{write_json, move_file};
+use crate::rpublish::articles_manager::ArticleStatus;
+use crate::rpublish::articles_manager::article::Article;
+use crate::rpublish::identity_manager::{Session, User};
+use crate::rpublish::metadata_cache::ArticleMetadata;
+
+use super::{is_safe_id, ArticleStore, MetadataStore, SessionStore, Storage, UserStore};
+
+const DATA_PATH: &str = "data";
+const JOURNAL_PATH: &str = "data/articles/journal";
+const TRASH_PATH: &str = "data/articles_trashcan";
+const USERS_PATH: &str = "data/auth/users.json";
+const SESSIONS_PATH: &str = "data/auth/sessions.json";
+
+// Json files under the data directory, one file per article and metadata entry
+pub struct FilesystemStorage;
+
+impl FilesystemStorage {
+    pub fn new() -> Self {
+        Self
+    }
+
+    // Finish the writes interrupted by a crash, only run by the server while it holds the data
+    // directory lock. Another process may be in the middle of these moves otherwise
+    pub fn recover() {
+        Self::recover_interrupted_moves(DATA_PATH);
+        Self::remove_duplicated_drafts();
+    }
+
+    fn article_path(article_id: &str, status: ArticleStatus) -> Result<String> {
+        if is_safe_id(article_id) {
+            Ok(format!("data/articles/{}/{}.json", status.dir_name(), article_id))
+        } else {
+            Err(Error::new(ErrorKind::InvalidInput, "Invalid article id"))
+        }
+    }
+
+    fn trash_path(article_id: &str, status: ArticleStatus) -> Result<String> {
+        if is_safe_id(article_id) {
+            Ok(format!("{}/{}/{}.json", TRASH_PATH, status.dir_name(), article_id))
+        } else {
+            Err(Error::new(ErrorKind::InvalidInput, "Invalid article id"))
+        }
+    }
+
+    fn metadata_path(cache_name: &str, article_id: &str) -> Result<String> {
+        if is_safe_id(article_id) && is_safe_id(cache_name) {
+            Ok(format!("data/cache/metadata/{}/{}.json", cache_name, article_id))
+        } else {
+            Err(Error::new(ErrorKind::InvalidInput, "Invalid metadata id"))
+        }
+    }
+
+    // Ids of the json files in a directory
+    fn read_ids(path: &Path) -> Vec<String> {
+        match fs::read_dir(path) {
+            Ok(files) => {
+                let mut ids: Vec<String> = Vec::new();
+                for file in files.filter_map(|file| file.ok()) {
+                    let file_path = file.path();
+                    if let Some(extension) = file_path.extension() {
+                        if extension == "json" {
+                            if let Some(stem) = file_path.file_stem().and_then(|stem| stem.to_str()) {
+                                ids.push(String::from(stem));
+                            }
+                        }
+                    }
+                }
+                ids
+            },
+            Err(_) => {
+                log::error!("Failed read files from {}", path.display());
+                Vec::new()
+            },
+        }
+    }
+
+    fn read_json<T: for<'de> Deserialize<'de>>(path: &str) -> Result<Option<T>> {
+        match File::open(path) {
+            Ok(file) => {
+                let reader = BufReader::new(file);
+                match serde_json::from_reader::<BufReader<File>, T>(reader) {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Error deserializing file {}", path)
+                    ))
+                }
+            },
+            Err(error) => {
+                match error.kind() {
+                    ErrorKind::NotFound => Ok(None),
+                    ErrorKind::PermissionDenied => Err(Error::new(ErrorKind::InvalidData, "File read error: Permision Denied")),
+                    _ => Err(Error::new(ErrorKind::InvalidData, format!("File read error not expected {}", error))),
+                }
+            }
+        }
+    }
+
+    fn write_value<T: Serialize>(path: &str, value: &T) -> Result<()> {
+        match serde_json::to_string(value) {
+            Ok(json) => write_json(path, json),
+            Err(error) => Err(Error::new(ErrorKind::InvalidData, error)),
+        }
+    }
+
+    fn remove_journal_entry(journal_path: &str) {
+        if let Err(error) = fs::remove_file(journal_path) {
+            log::error!("Failed to remove move journal {}: {}", journal_path, error);
+        }
+    }
+
+    // Finish the publish and unpublish moves recorded in the journal of the data directory,
+    // their metadata is dropped so it is rebuilt from the article files
+    fn recover_interrupted_moves(data_path: &str) {
+        let journal_files = match fs::read_dir(format!("{}/articles/journal", data_path)) {
+            Ok(journal_files) => journal_files,
+            Err(_) => return,
+        };
+        for file in journal_files.filter_map(|file| file.ok()) {
+            let journal_path = file.path();
+            if journal_path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let article_id = match journal_path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(article_id) => article_id.to_string(),
+                None => continue,
+            };
+            let pending_move = fs::read_to_string(&journal_path).ok()
+                .and_then(|json| serde_json::from_str::<PendingMove>(&json).ok());
+
+            if let Some(pending_move) = pending_move {
+                let origin_path = format!("{}/articles/{}/{}.json", data_path, pending_move.origin.dir_name(), article_id);
+                let target_path = format!("{}/articles/{}/{}.json", data_path, pending_move.target.dir_name(), article_id);
+                let origin_exists = Path::new(&origin_path).exists();
+                let target_exists = Path::new(&target_path).exists();
+
+                if origin_exists {
+                    match move_file(&origin_path, &target_path) {
+                        Ok(_) => log::info!("Finished interrupted move of article {} to {}", article_id, pending_move.target.dir_name()),
+                        Err(error) => {
+                            log::error!("Failed to finish move of article {}: {}", article_id, error);
+                            continue;
+                        },
+                    }
+                } else if !target_exists {
+                    log::error!("Article {} is missing in both draft and published after an interrupted move", article_id);
+                }
+
+                for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+                    let _ = fs::remove_file(format!("{}/cache/metadata/{}/{}.json", data_path, status.dir_name(), article_id));
+                }
+            }
+            Self::remove_journal_entry(journal_path.to_str().unwrap_or_default());
+        }
+    }
+
+    // Copy and delete moves from older versions could leave the same article as draft and
+    // published, an identical draft holds no changes so it is removed
+    fn remove_duplicated_drafts() {
+        for article_id in Self::read_ids(Path::new("data/articles/draft")) {
+            let draft_path = format!("data/articles/draft/{}.json", article_id);
+            let published_path = format!("data/articles/published/{}.json", article_id);
+            if let (Ok(draft), Ok(published)) = (fs::read(&draft_path), fs::read(&published_path)) {
+                if draft == published {
+                    match fs::remove_file(&draft_path) {
+                        Ok(_) => log::info!("Removed draft of {} identical to its published version", article_id),
+                        Err(error) => log::error!("Failed to remove duplicated draft {}: {}", article_id, error),
+                    }
+                    let _ = fs::remove_file(format!("data/cache/metadata/draft/{}.json", article_id));
+                }
+            }
+        }
+    }
+}
+
+impl ArticleStore for FilesystemStorage {
+    fn list_articles(&self, status: ArticleStatus) -> Vec<String> {
+        Self::read_ids(Path::new(&format!("data/articles/{}", status.dir_name())))
+    }
+
+    fn read_article(&self, article_id: &str, status: ArticleStatus) -> Option<Article> {
+        let path = Self::article_path(article_id, status).ok()?;
+        let article_string = fs::read_to_string(path).ok()?;
+        serde_json::from_str::<Article>(article_string.as_str()).ok()
+    }
+
+    fn write_article(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()> {
+        Self::write_value(&Self::article_path(article_id, status)?, article)
+    }
+
+    fn delete_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        fs::remove_file(Self::article_path(article_id, status)?)
+    }
+
+    fn move_article(&self, article_id: &str, origin: ArticleStatus, target: ArticleStatus) -> Result<()> {
+        let origin_path = Self::article_path(article_id, origin)?;
+        let target_path = Self::article_path(article_id, target)?;
+
+        // Record the move so it can be finished on startup if the server stops halfway
+        let journal_path = format!("{}/{}.json", JOURNAL_PATH, article_id);
+        Self::write_value(&journal_path, &PendingMove { origin, target })?;
+
+        let result = move_file(&origin_path, &target_path);
+        Self::remove_journal_entry(&journal_path);
+        result
+    }
+
+    fn list_trashed(&self, status: ArticleStatus) -> Vec<String> {
+        Self::read_ids(Path::new(&format!("{}/{}", TRASH_PATH, status.dir_name())))
+    }
+
+    fn trash_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        move_file(&Self::article_path(article_id, status)?, &Self::trash_path(article_id, status)?)
+    }
+
+    fn restore_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        let article_path = Self::article_path(article_id, status)?;
+        if Path::new(&article_path).exists() {
+            return Err(Error::new(ErrorKind::AlreadyExists, "Article already exists"));
+        }
+        move_file(&Self::trash_path(article_id, status)?, &article_path)
+    }
+
+    fn read_trashed(&self, article_id: &str, status: ArticleStatus) -> Option<Article> {
+        let article_string = fs::read_to_string(Self::trash_path(article_id, status).ok()?).ok()?;
+        serde_json::from_str::<Article>(article_string.as_str()).ok()
+    }
+
+    fn write_trashed(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()> {
+        Self::write_value(&Self::trash_path(article_id, status)?, article)
+    }
+
+    fn delete_trashed(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        fs::remove_file(Self::trash_path(article_id, status)?)
+    }
+}
+
+impl MetadataStore for FilesystemStorage {
+    fn load_metadata(&self, cache_name: &str) -> HashMap<String, ArticleMetadata> {
+        let mut articles: HashMap<String, ArticleMetadata> = HashMap::new();
+        let cache_path = format!("data/cache/metadata/{}", cache_name);
+        for article_id in Self::read_ids(Path::new(&cache_path)) {
+            let file_path = format!("{}/{}.json", cache_path, article_id);
+            match fs::read_to_string(&file_path) {
+                Ok(metadata_string) => {
+                    match serde_json::from_str::<ArticleMetadata>(metadata_string.as_str()) {
+                        Ok(article_metadata) => {
+                            articles.insert(article_id, article_metadata);
+                        },
+                        Err(_) => {
+                            log::error!("Failed to parse {}.json", article_id);
+                        }
+                    }
+                },
+                Err(_) => log::error!("Cannot read {} as string", file_path),
+            }
+        }
+        articles
+    }
+
+    fn save_metadata(&self, cache_name: &str, article_id: &str, metadata: &ArticleMetadata) -> Result<()> {
+        Self::write_value(&Self::metadata_path(cache_name, article_id)?, metadata)
+    }
+
+    fn remove_metadata(&self, cache_name: &str, article_id: &str) -> Result<()> {
+        match fs::remove_file(Self::metadata_path(cache_name, article_id)?) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UsersFile {
+    users: Vec<User>
+}
+
+#[derive(Deserialize)]
+struct SessionsFile {
+    sessions: HashMap<String, Session>
+}
+
+impl UserStore for FilesystemStorage {
+    fn load_users(&self) -> Result<Option<Vec<User>>> {
+        Ok(Self::read_json::<UsersFile>(USERS_PATH)?.map(|file| file.users))
+    }
+
+    fn save_users(&self, users: &[User]) -> Result<()> {
+        Self::write_value(USERS_PATH, &json!({ "users": users }))
+    }
+}
+
+impl SessionStore for FilesystemStorage {
+    fn load_sessions(&self) -> Result<Option<HashMap<String, Session>>> {
+        Ok(Self::read_json::<SessionsFile>(SESSIONS_PATH)?.map(|file| file.sessions))
+    }
+
+    fn save_sessions(&self, sessions: &HashMap<String, Session>) -> Result<()> {
+        Self::write_value(SESSIONS_PATH, &json!({ "sessions": sessions }))
+    }
+}
+
+impl Storage for FilesystemStorage {
+    fn name(&self) -> &'static str {
+        "filesystem"
+    }
+}
+
+// Move between draft and published in progress
+#[derive(Serialize, Deserialize)]
+struct PendingMove {
+    origin: ArticleStatus,
+    target: ArticleStatus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Data directory with the article, journal and metadata directories, removed by the test
+    fn data_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rpublish-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub_dir in ["articles/draft", "articles/published", "articles/journal", "cache/metadata/draft", "cache/metadata/published"] {
+            fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+        dir.to_string_lossy().to_string()
+    }
+
+    fn journal(data_path: &str, article_id: &str, origin: ArticleStatus, target: ArticleStatus) {
+        let json = serde_json::to_string(&PendingMove { origin, target }).unwrap();
+        fs::write(format!("{}/articles/journal/{}.json", data_path, article_id), json).unwrap();
+    }
+
+    fn exists(data_path: &str, path: &str) -> bool {
+        Path::new(&format!("{}/{}", data_path, path)).exists()
+    }
+
+    #[test]
+    fn interrupted_publish_is_finished() {
+        let data_path = data_dir("finish");
+        fs::write(format!("{}/articles/draft/a1.json", data_path), "{}").unwrap();
+        fs::write(format!("{}/cache/metadata/draft/a1.json", data_path), "{}").unwrap();
+        journal(&data_path, "a1", ArticleStatus::Draft, ArticleStatus::Published);
+
+        FilesystemStorage::recover_interrupted_moves(&data_path);
+
+        assert!(!exists(&data_path, "articles/draft/a1.json"));
+        assert!(exists(&data_path, "articles/published/a1.json"));
+        assert!(!exists(&data_path, "articles/journal/a1.json"));
+        // Dropped so it is rebuilt from the moved article
+        assert!(!exists(&data_path, "cache/metadata/draft/a1.json"));
+        fs::remove_dir_all(&data_path).unwrap();
+    }
+
+    #[test]
+    fn completed_move_only_clears_the_journal() {
+        let data_path = data_dir("completed");
+        fs::write(format!("{}/articles/draft/a2.json", data_path), "{\"kept\": true}").unwrap();
+        journal(&data_path, "a2", ArticleStatus::Published, ArticleStatus::Draft);
+
+        FilesystemStorage::recover_interrupted_moves(&data_path);
+
+        assert_eq!(fs::read_to_string(format!("{}/articles/draft/a2.json", data_path)).unwrap(), "{\"kept\": true}");
+        assert!(!exists(&data_path, "articles/published/a2.json"));
+        assert!(!exists(&data_path, "articles/journal/a2.json"));
+        fs::remove_dir_all(&data_path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_journal_entries_are_removed() {
+        let data_path = data_dir("unreadable");
+        fs::write(format!("{}/articles/published/a3.json", data_path), "{}").unwrap();
+        fs::write(format!("{}/articles/journal/a3.json", data_path), "{\"origin\":").unwrap();
+
+        FilesystemStorage::recover_interrupted_moves(&data_path);
+
+        assert!(exists(&data_path, "articles/published/a3.json"));
+        assert!(!exists(&data_path, "articles/journal/a3.json"));
+        fs::remove_dir_all(&data_path).unwrap();
+    }
+}
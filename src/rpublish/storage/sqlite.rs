@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Mutex;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+use serde::{Serialize, Deserialize};
+
+use crate::locks::MutexRecover;
+use crate::rpublish::articles_manager::ArticleStatus;
+use crate::rpublish::articles_manager::article::Article;
+use crate::rpublish::identity_manager::{Session, User};
+use crate::rpublish::metadata_cache::ArticleMetadata;
+
+use super::{ArticleStore, MetadataStore, SessionStore, Storage, UserStore};
+
+// Documents are stored as json in a single sqlite database file, every change runs in a transaction
+pub struct SqliteStorage {
+    connection: Mutex<Connection>
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS articles (
+        id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (id, status)
+    );
+    CREATE TABLE IF NOT EXISTS trash (
+        id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (id, status)
+    );
+    CREATE TABLE IF NOT EXISTS metadata (
+        cache TEXT NOT NULL,
+        id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (cache, id)
+    );
+    CREATE TABLE IF NOT EXISTS users (
+        position INTEGER PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        sessid TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS stores (
+        name TEXT PRIMARY KEY
+    );
+";
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path).map_err(to_io_error)?;
+        connection.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL;").map_err(to_io_error)?;
+        connection.execute_batch(SCHEMA).map_err(to_io_error)?;
+        log::info!("Sqlite storage opened at {}", path);
+        Ok(Self {
+            connection: Mutex::new(connection)
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A panic while holding the connection doesn't leave it in a broken state
+        self.connection.lock_or_recover()
+    }
+
+    // Users and sessions are saved as a whole, remember which ones were saved to tell
+    // an empty store from one never saved
+    fn mark_saved(connection: &Connection, store: &str) -> rusqlite::Result<usize> {
+        connection.execute("INSERT OR IGNORE INTO stores (name) VALUES (?1)", params![store])
+    }
+
+    // Move an article row between the articles and trash tables, the target row is replaced
+    // Move an article row between tables in one transaction. With keep_target an article already
+    // in the target is an error, checked in the same transaction so no other writer gets in between
+    fn move_between(connection: &mut Connection, origin: &str, target: &str, article_id: &str, status: ArticleStatus, keep_target: bool) -> Result<()> {
+        // Immediate takes the write lock up front, other processes wait until the move is done
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate).map_err(to_io_error)?;
+        if keep_target {
+            let exists = transaction
+                .query_row(&format!("SELECT 1 FROM {} WHERE id = ?1 AND status = ?2", target), params![article_id, status.dir_name()], |_| Ok(()))
+                .optional()
+                .map_err(to_io_error)?
+                .is_some();
+            if exists {
+                return Err(Error::new(ErrorKind::AlreadyExists, "Article already exists"));
+            }
+        }
+        let moved = transaction
+            .execute(
+                &format!("INSERT OR REPLACE INTO {} (id, status, data) SELECT id, status, data FROM {} WHERE id = ?1 AND status = ?2", target, origin),
+                params![article_id, status.dir_name()]
+            )
+            .map_err(to_io_error)?;
+        if moved == 0 {
+            return Err(Error::new(ErrorKind::NotFound, "Article not found"));
+        }
+        transaction
+            .execute(&format!("DELETE FROM {} WHERE id = ?1 AND status = ?2", origin), params![article_id, status.dir_name()])
+            .map_err(to_io_error)?;
+        transaction.commit().map_err(to_io_error)
+    }
+
+    // Articles and trashed articles have the same columns, table is one of them
+    fn read_from(&self, table: &str, article_id: &str, status: ArticleStatus) -> Option<Article> {
+        let data: Option<String> = self.connection()
+            .query_row(
+                &format!("SELECT data FROM {} WHERE id = ?1 AND status = ?2", table),
+                params![article_id, status.dir_name()],
+                |row| row.get(0)
+            )
+            .optional()
+            .ok()?;
+        serde_json::from_str::<Article>(&data?).ok()
+    }
+
+    fn write_into(&self, table: &str, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()> {
+        let data = to_json(article)?;
+        self.connection()
+            .execute(
+                &format!("INSERT OR REPLACE INTO {} (id, status, data) VALUES (?1, ?2, ?3)", table),
+                params![article_id, status.dir_name(), data]
+            )
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+
+    fn delete_from(&self, table: &str, article_id: &str, status: ArticleStatus) -> Result<()> {
+        let deleted = self.connection()
+            .execute(&format!("DELETE FROM {} WHERE id = ?1 AND status = ?2", table), params![article_id, status.dir_name()])
+            .map_err(to_io_error)?;
+        if deleted == 0 {
+            Err(Error::new(ErrorKind::NotFound, "Article not found"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_saved(&self, store: &str) -> Result<bool> {
+        self.connection()
+            .query_row("SELECT 1 FROM stores WHERE name = ?1", params![store], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+            .map_err(to_io_error)
+    }
+}
+
+impl ArticleStore for SqliteStorage {
+    fn list_articles(&self, status: ArticleStatus) -> Vec<String> {
+        let connection = self.connection();
+        let ids = connection.prepare("SELECT id FROM articles WHERE status = ?1")
+            .and_then(|mut statement| {
+                statement.query_map(params![status.dir_name()], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()
+            });
+        match ids {
+            Ok(ids) => ids,
+            Err(error) => {
+                log::error!("Failed to list {} articles: {}", status.dir_name(), error);
+                Vec::new()
+            },
+        }
+    }
+
+    fn read_article(&self, article_id: &str, status: ArticleStatus) -> Option<Article> {
+        self.read_from("articles", article_id, status)
+    }
+
+    fn write_article(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()> {
+        self.write_into("articles", article_id, article, status)
+    }
+
+    fn delete_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        self.delete_from("articles", article_id, status)
+    }
+
+    fn move_article(&self, article_id: &str, origin: ArticleStatus, target: ArticleStatus) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(to_io_error)?;
+        transaction
+            .execute("DELETE FROM articles WHERE id = ?1 AND status = ?2", params![article_id, target.dir_name()])
+            .map_err(to_io_error)?;
+        let moved = transaction
+            .execute(
+                "UPDATE articles SET status = ?3 WHERE id = ?1 AND status = ?2",
+                params![article_id, origin.dir_name(), target.dir_name()]
+            )
+            .map_err(to_io_error)?;
+        if moved == 0 {
+            return Err(Error::new(ErrorKind::NotFound, "Article not found"));
+        }
+        transaction.commit().map_err(to_io_error)
+    }
+
+    fn list_trashed(&self, status: ArticleStatus) -> Vec<String> {
+        let connection = self.connection();
+        let ids = connection.prepare("SELECT id FROM trash WHERE status = ?1")
+            .and_then(|mut statement| {
+                statement.query_map(params![status.dir_name()], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()
+            });
+        match ids {
+            Ok(ids) => ids,
+            Err(error) => {
+                log::error!("Failed to list trashed {} articles: {}", status.dir_name(), error);
+                Vec::new()
+            },
+        }
+    }
+
+    fn trash_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        Self::move_between(&mut self.connection(), "articles", "trash", article_id, status, false)
+    }
+
+    fn restore_article(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        Self::move_between(&mut self.connection(), "trash", "articles", article_id, status, true)
+    }
+
+    fn read_trashed(&self, article_id: &str, status: ArticleStatus) -> Option<Article> {
+        self.read_from("trash", article_id, status)
+    }
+
+    fn write_trashed(&self, article_id: &str, article: &Article, status: ArticleStatus) -> Result<()> {
+        self.write_into("trash", article_id, article, status)
+    }
+
+    fn delete_trashed(&self, article_id: &str, status: ArticleStatus) -> Result<()> {
+        self.delete_from("trash", article_id, status)
+    }
+}
+
+impl MetadataStore for SqliteStorage {
+    fn load_metadata(&self, cache_name: &str) -> HashMap<String, ArticleMetadata> {
+        let connection = self.connection();
+        let rows = connection.prepare("SELECT id, data FROM metadata WHERE cache = ?1")
+            .and_then(|mut statement| {
+                statement.query_map(params![cache_name], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<rusqlite::Result<Vec<(String, String)>>>()
+            });
+        let mut articles: HashMap<String, ArticleMetadata> = HashMap::new();
+        match rows {
+            Ok(rows) => {
+                for (article_id, data) in rows {
+                    match serde_json::from_str::<ArticleMetadata>(&data) {
+                        Ok(article_metadata) => {
+                            articles.insert(article_id, article_metadata);
+                        },
+                        Err(_) => log::error!("Failed to parse metadata of {}", article_id),
+                    }
+                }
+            },
+            Err(error) => log::error!("Failed to load {} metadata: {}", cache_name, error),
+        }
+        articles
+    }
+
+    fn save_metadata(&self, cache_name: &str, article_id: &str, metadata: &ArticleMetadata) -> Result<()> {
+        let data = to_json(metadata)?;
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO metadata (cache, id, data) VALUES (?1, ?2, ?3)",
+                params![cache_name, article_id, data]
+            )
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+
+    fn remove_metadata(&self, cache_name: &str, article_id: &str) -> Result<()> {
+        self.connection()
+            .execute("DELETE FROM metadata WHERE cache = ?1 AND id = ?2", params![cache_name, article_id])
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+}
+
+impl UserStore for SqliteStorage {
+    fn load_users(&self) -> Result<Option<Vec<User>>> {
+        if !self.is_saved("users")? {
+            return Ok(None);
+        }
+        let connection = self.connection();
+        let rows = connection.prepare("SELECT data FROM users ORDER BY position")
+            .and_then(|mut statement| {
+                statement.query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()
+            })
+            .map_err(to_io_error)?;
+        let users = rows.iter()
+            .map(|data| from_json::<User>(data))
+            .collect::<Result<Vec<User>>>()?;
+        Ok(Some(users))
+    }
+
+    fn save_users(&self, users: &[User]) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(to_io_error)?;
+        transaction.execute("DELETE FROM users", []).map_err(to_io_error)?;
+        for (position, user) in users.iter().enumerate() {
+            transaction
+                .execute("INSERT INTO users (position, data) VALUES (?1, ?2)", params![position as i64, to_json(user)?])
+                .map_err(to_io_error)?;
+        }
+        Self::mark_saved(&transaction, "users").map_err(to_io_error)?;
+        transaction.commit().map_err(to_io_error)
+    }
+}
+
+impl SessionStore for SqliteStorage {
+    fn load_sessions(&self) -> Result<Option<HashMap<String, Session>>> {
+        if !self.is_saved("sessions")? {
+            return Ok(None);
+        }
+        let connection = self.connection();
+        let rows = connection.prepare("SELECT sessid, data FROM sessions")
+            .and_then(|mut statement| {
+                statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<rusqlite::Result<Vec<(String, String)>>>()
+            })
+            .map_err(to_io_error)?;
+        let mut sessions: HashMap<String, Session> = HashMap::new();
+        for (sessid, data) in rows {
+            sessions.insert(sessid, from_json::<Session>(&data)?);
+        }
+        Ok(Some(sessions))
+    }
+
+    fn save_sessions(&self, sessions: &HashMap<String, Session>) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(to_io_error)?;
+        transaction.execute("DELETE FROM sessions", []).map_err(to_io_error)?;
+        for (sessid, session) in sessions {
+            transaction
+                .execute("INSERT INTO sessions (sessid, data) VALUES (?1, ?2)", params![sessid, to_json(session)?])
+                .map_err(to_io_error)?;
+        }
+        Self::mark_saved(&transaction, "sessions").map_err(to_io_error)?;
+        transaction.commit().map_err(to_io_error)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+}
+
+fn to_io_error(error: rusqlite::Error) -> Error {
+    Error::other(format!("Sqlite error: {}", error))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(data: &str) -> Result<T> {
+    serde_json::from_str::<T>(data).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::rpublish::storage::ArticleStore;
+
+    fn article(title: &str) -> Article {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "author": "alice",
+            "data": "",
+            "tags": [],
+            "created_date": Utc::now(),
+            "update_date": Utc::now()
+        })).unwrap()
+    }
+
+    #[test]
+    fn restore_refuses_an_article_written_by_another_connection() {
+        let path = std::env::temp_dir().join(format!("rpublish-sqlite-restore-{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_str().unwrap();
+        // Two connections to the same file, like the server and the command line
+        let server = SqliteStorage::open(path).unwrap();
+        let command_line = SqliteStorage::open(path).unwrap();
+
+        server.write_article("article-1", &article("Old"), ArticleStatus::Draft).unwrap();
+        server.trash_article("article-1", ArticleStatus::Draft).unwrap();
+        command_line.write_article("article-1", &article("New"), ArticleStatus::Draft).unwrap();
+
+        let error = server.restore_article("article-1", ArticleStatus::Draft).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(server.list_trashed(ArticleStatus::Draft), vec![String::from("article-1")]);
+        assert_eq!(server.read_article("article-1", ArticleStatus::Draft).unwrap().title, "New");
+
+        command_line.delete_article("article-1", ArticleStatus::Draft).unwrap();
+        server.restore_article("article-1", ArticleStatus::Draft).unwrap();
+        assert_eq!(server.read_article("article-1", ArticleStatus::Draft).unwrap().title, "Old");
+        assert!(server.list_trashed(ArticleStatus::Draft).is_empty());
+
+        drop((server, command_line));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::thread;
+use notify::{EventKind, RecursiveMode, Watcher};
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+
+use super::RPublishApp;
+use super::articles_manager::ArticleStatus;
+use super::articles_manager::article::Article;
+
+// Pick up article files added, changed or removed outside the server, like a git pull
+// into data/articles. Only used with the filesystem storage
+pub fn watch_articles(app: Arc<RPublishApp>) -> notify::Result<()> {
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for status in [ArticleStatus::Draft, ArticleStatus::Published] {
+        watcher.watch(Path::new(&format!("data/articles/{}", status.dir_name())), RecursiveMode::NonRecursive)?;
+    }
+
+    thread::spawn(move || {
+        // The watcher stops when dropped, keep it with the thread
+        let _watcher = watcher;
+        for event in receiver {
+            match event {
+                // Files are read once written and closed, or renamed and removed
+                Ok(event) if matches!(
+                    event.kind,
+                    EventKind::Access(AccessKind::Close(AccessMode::Write)) | EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(_)
+                ) => {
+                    for path in event.paths {
+                        reload_path(&app, &path);
+                    }
+                },
+                Ok(_) => {},
+                Err(error) => log::error!("Articles watcher error: {}", error),
+            }
+        }
+    });
+
+    log::info!("Watching data/articles for external changes");
+    Ok(())
+}
+
+fn reload_path(app: &RPublishApp, path: &Path) {
+    // Temporary files of atomic writes are renamed into place, wait for the json file
+    if path.extension().is_none_or(|extension| extension != "json") {
+        return;
+    }
+    let status = match path.parent().and_then(|parent| parent.file_name()).and_then(|name| name.to_str()) {
+        Some("draft") => ArticleStatus::Draft,
+        Some("published") => ArticleStatus::Published,
+        _ => return,
+    };
+    let article_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(article_id) => article_id,
+        None => return,
+    };
+
+    // Files that don't parse are left out, the listed version is kept until they are fixed
+    if path.exists() {
+        let parsed = fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|json| serde_json::from_str::<Article>(&json).map_err(|error| error.to_string()));
+        if let Err(error) = parsed {
+            log::error!("Failed to parse {}: {}", path.display(), error);
+            return;
+        }
+    }
+
+    app.articles_manager.reload_article(article_id, status);
+}
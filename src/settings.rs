@@ -0,0 +1,231 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono::format::{Item, StrftimeItems};
+use serde::{Serialize, Deserialize};
+
+use crate::config::LOCAL_BASE_URL;
+use crate::error::FieldError;
+use crate::helpers::write_json;
+
+const SETTINGS_PATH: &str = "data/settings.json";
+
+const MAX_TEXT_LENGTH: usize = 300;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_POSTS_PER_PAGE: usize = 100;
+const MAX_SOCIAL_LINKS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationPolicy {
+    // Users are only added by admins
+    #[default]
+    Closed,
+    Open,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentPolicy {
+    #[default]
+    Disabled,
+    Moderated,
+    Open,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SocialLink {
+    pub name: String,
+    pub url: String
+}
+
+// Public site settings stored in data/settings.json and edited by admins in the dashboard,
+// missing fields take their default value
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SiteSettings {
+    pub name: String,
+    pub description: String,
+    // Absolute url of the site used in the feed, sitemap and social tags,
+    // the local server address when empty
+    pub base_url: String,
+    // Shown for articles without author
+    pub default_author: String,
+    pub posts_per_page: usize,
+    // Fixed offset of the displayed dates, "UTC" or like "+02:00"
+    pub timezone: String,
+    // strftime format of the displayed dates
+    pub date_format: String,
+    pub social_links: Vec<SocialLink>,
+    // Given to the themes, rpublish has no sign up or comments of its own
+    pub registration: RegistrationPolicy,
+    pub comments: CommentPolicy
+}
+
+impl Default for SiteSettings {
+    fn default() -> Self {
+        Self {
+            name: String::from("rpublish"),
+            description: String::new(),
+            base_url: String::new(),
+            default_author: String::new(),
+            posts_per_page: 10,
+            timezone: String::from("UTC"),
+            date_format: String::from("%B %e, %Y"),
+            social_links: Vec::new(),
+            registration: RegistrationPolicy::default(),
+            comments: CommentPolicy::default()
+        }
+    }
+}
+
+// "UTC", "Z" or an offset like "+02:00", "-0530"
+pub fn parse_offset(timezone: &str) -> Option<FixedOffset> {
+    let timezone = timezone.trim();
+    if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match timezone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = timezone[1..].chars().filter(|character| *character != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|character| character.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+pub fn is_web_url(url: &str) -> bool {
+    let host = match url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) {
+        Some(rest) => rest.split(['/', '?', '#']).next().unwrap_or_default(),
+        None => return false,
+    };
+    !host.is_empty() && !url.contains(char::is_whitespace)
+}
+
+impl SiteSettings {
+    // Never taken from the request Host header, any client could put its own host in the
+    // canonical and feed links cached by a proxy
+    pub fn public_base_url(&self) -> String {
+        if self.base_url.is_empty() {
+            LOCAL_BASE_URL.to_string()
+        } else {
+            self.base_url.to_owned()
+        }
+    }
+
+    // Invalid settings files are logged and replaced by the defaults until they are saved again
+    pub fn load() -> Self {
+        match fs::read_to_string(SETTINGS_PATH) {
+            Ok(settings_string) => {
+                let mut settings = match serde_json::from_str::<Self>(&settings_string) {
+                    Ok(settings) => settings,
+                    Err(error) => {
+                        log::error!("Failed to parse {}, using defaults: {}", SETTINGS_PATH, error);
+                        return Self::default();
+                    },
+                };
+                match settings.validate() {
+                    Ok(_) => settings,
+                    Err(errors) => {
+                        for error in errors {
+                            log::error!("Invalid setting {} in {}: {}", error.field, SETTINGS_PATH, error.message);
+                        }
+                        log::error!("Using the default site settings");
+                        Self::default()
+                    },
+                }
+            },
+            Err(error) => {
+                if error.kind() != ErrorKind::NotFound {
+                    log::error!("Error reading {}, using defaults: {}", SETTINGS_PATH, error);
+                }
+                Self::default()
+            },
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        write_json(SETTINGS_PATH, json)
+    }
+
+    // Trim the text fields and check every field, all the problems are returned at once
+    pub fn validate(&mut self) -> std::result::Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            errors.push(FieldError::new("name", "The site needs a name"));
+        } else if self.name.chars().count() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", &format!("Longer than {} characters", MAX_NAME_LENGTH)));
+        }
+
+        self.description = self.description.trim().to_string();
+        if self.description.chars().count() > MAX_TEXT_LENGTH {
+            errors.push(FieldError::new("description", &format!("Longer than {} characters", MAX_TEXT_LENGTH)));
+        }
+
+        self.base_url = self.base_url.trim().trim_end_matches('/').to_string();
+        if !self.base_url.is_empty() && !is_web_url(&self.base_url) {
+            errors.push(FieldError::new("base_url", "Must be an http or https url like https://example.com/blog"));
+        }
+
+        self.default_author = self.default_author.trim().to_string();
+        if self.default_author.chars().count() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("default_author", &format!("Longer than {} characters", MAX_NAME_LENGTH)));
+        }
+
+        if self.posts_per_page == 0 || self.posts_per_page > MAX_POSTS_PER_PAGE {
+            errors.push(FieldError::new("posts_per_page", &format!("Must be between 1 and {}", MAX_POSTS_PER_PAGE)));
+        }
+
+        self.timezone = self.timezone.trim().to_string();
+        if parse_offset(&self.timezone).is_none() {
+            errors.push(FieldError::new("timezone", "Must be UTC or an offset like +02:00"));
+        }
+
+        if self.date_format.trim().is_empty() || self.date_format.len() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("date_format", "Must be a strftime format like %B %e, %Y"));
+        } else if StrftimeItems::new(&self.date_format).any(|item| matches!(item, Item::Error)) {
+            errors.push(FieldError::new("date_format", "Unknown % specifier, see the strftime format of chrono"));
+        }
+
+        if self.social_links.len() > MAX_SOCIAL_LINKS {
+            errors.push(FieldError::new("social_links", &format!("No more than {} links", MAX_SOCIAL_LINKS)));
+        }
+        for (position, link) in self.social_links.iter_mut().enumerate() {
+            link.name = link.name.trim().to_string();
+            link.url = link.url.trim().to_string();
+            if link.name.is_empty() || link.name.chars().count() > MAX_NAME_LENGTH {
+                errors.push(FieldError::new(&format!("social_links[{}].name", position), "The link needs a name"));
+            }
+            if !is_web_url(&link.url) && !link.url.starts_with("mailto:") {
+                errors.push(FieldError::new(&format!("social_links[{}].url", position), "Must be an http, https or mailto url"));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // Dates shown in the pages, settings are validated so the format is known to be valid
+    pub fn display_date(&self, date: &DateTime<Utc>) -> String {
+        let offset = parse_offset(&self.timezone).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        date.with_timezone(&offset).format(&self.date_format).to_string()
+    }
+
+    pub fn author(&self, author: &str) -> String {
+        if author.trim().is_empty() {
+            self.default_author.to_owned()
+        } else {
+            author.to_string()
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use serde::Serialize;
+use tera::Context;
+
+use crate::rpublish::articles_cache::CachedArticle;
+use crate::rpublish::editorjs::escape_html;
+use crate::rpublish::metadata_cache::ArticleMetadata;
+use crate::rpublish::seo::ArticleSeo;
+use crate::settings::SiteSettings;
+
+// Public site pages, rendered the same way by the server and by the static export
+
+// Latest articles included in the feed
+const FEED_SIZE: usize = 20;
+
+// How the pages link to each other and to the static files
+#[derive(Clone)]
+pub enum SiteLinks {
+    // Served by rpublish, "/article/{id}"
+    Server,
+    // Exported to be served from a base url, "{base_url}/article/{id}/"
+    BaseUrl(String),
+    // Exported to be opened from any location, relative to a page the given number
+    // of directories below the root, "../../article/{id}/index.html"
+    Relative(usize),
+}
+
+impl SiteLinks {
+    pub fn base_url(base_url: &str) -> Self {
+        SiteLinks::BaseUrl(base_url.trim_end_matches('/').to_string())
+    }
+
+    // Links of the page at the given path
+    pub fn at(&self, path: &str) -> Self {
+        match self {
+            SiteLinks::Relative(_) => SiteLinks::Relative(path.split('/').filter(|segment| !segment.is_empty()).count()),
+            links => links.to_owned(),
+        }
+    }
+
+    // Prefix of the static files, "public/..." and "theme/..."
+    pub fn root(&self) -> String {
+        match self {
+            SiteLinks::Server => String::from("/"),
+            SiteLinks::BaseUrl(base_url) => format!("{}/", base_url),
+            SiteLinks::Relative(depth) => "../".repeat(*depth),
+        }
+    }
+
+    // Url of the page at the given path, "" is the home page
+    pub fn page(&self, path: &str) -> String {
+        match self {
+            SiteLinks::Server => format!("/{}", path),
+            SiteLinks::BaseUrl(base_url) if path.is_empty() => format!("{}/", base_url),
+            SiteLinks::BaseUrl(base_url) => format!("{}/{}/", base_url, path),
+            SiteLinks::Relative(_) if path.is_empty() => format!("{}index.html", self.root()),
+            SiteLinks::Relative(_) => format!("{}{}/index.html", self.root(), path),
+        }
+    }
+
+    // Images and files linked from the site root like "/uploads/..." follow the static files
+    pub fn asset(&self, url: &str) -> String {
+        match url.strip_prefix('/') {
+            Some(path) if !path.starts_with('/') => format!("{}{}", self.root(), path),
+            _ => url.to_string(),
+        }
+    }
+
+    // Rewrite the root relative src and href attributes of rendered article html
+    fn rebase_html(&self, html: &str) -> String {
+        if let SiteLinks::Server = self {
+            return html.to_string();
+        }
+        let root = self.root();
+        let mut rebased = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(position) = rest.find("=\"/") {
+            let (before, after) = rest.split_at(position + 2);
+            rebased.push_str(before);
+            let attribute = before.trim_end_matches("=\"");
+            if (attribute.ends_with("src") || attribute.ends_with("href")) && !after.starts_with("//") {
+                rebased.push_str(&root);
+                rest = &after[1..];
+            } else {
+                rest = after;
+            }
+        }
+        rebased.push_str(rest);
+        rebased
+    }
+}
+
+pub fn article_path(article_id: &str) -> String {
+    format!("article/{}", article_id)
+}
+
+// Home or tag listing page, the first page has no page number
+pub fn listing_path(tag_slug: Option<&str>, page: usize) -> String {
+    let base = match tag_slug {
+        Some(slug) => format!("tag/{}", slug),
+        None => String::new(),
+    };
+    match (base.is_empty(), page) {
+        (_, 1) => base,
+        (true, page) => format!("page/{}", page),
+        (false, page) => format!("{}/page/{}", base, page),
+    }
+}
+
+// Tags are linked by a lowercase slug, None when nothing is left to link
+pub fn tag_slug(tag: &str) -> Option<String> {
+    let slug = tag.to_lowercase()
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+    if slug.is_empty() { None } else { Some(slug) }
+}
+
+// Tagged articles grouped by slug with the name of the tag as first written
+pub fn tags(articles: &[(String, ArticleMetadata)]) -> BTreeMap<String, (String, Vec<(String, ArticleMetadata)>)> {
+    let mut tags: BTreeMap<String, (String, Vec<(String, ArticleMetadata)>)> = BTreeMap::new();
+    for (article_id, metadata) in articles {
+        let mut slugs: Vec<(String, &String)> = metadata.tags.iter()
+            .filter_map(|tag| tag_slug(tag).map(|slug| (slug, tag)))
+            .collect();
+        slugs.sort_by(|a, b| a.0.cmp(&b.0));
+        slugs.dedup_by(|a, b| a.0 == b.0);
+        for (slug, tag) in slugs {
+            tags.entry(slug)
+                .or_insert_with(|| (tag.to_owned(), Vec::new()))
+                .1.push((article_id.to_owned(), metadata.to_owned()));
+        }
+    }
+    tags
+}
+
+pub fn page_count(articles: usize, settings: &SiteSettings) -> usize {
+    articles.div_ceil(settings.posts_per_page.max(1)).max(1)
+}
+
+#[derive(Serialize)]
+struct TagLink {
+    name: String,
+    url: String
+}
+
+#[derive(Serialize)]
+struct ListedArticle<'a> {
+    id: &'a str,
+    url: String,
+    display_date: String,
+    display_author: String,
+    cover_url: Option<String>,
+    tag_links: Vec<TagLink>,
+    #[serde(flatten)]
+    metadata: &'a ArticleMetadata
+}
+
+fn tag_links(links: &SiteLinks, tags: &[String]) -> Vec<TagLink> {
+    tags.iter()
+        .filter_map(|tag| tag_slug(tag).map(|slug| TagLink {
+            name: tag.to_owned(),
+            url: links.page(&listing_path(Some(&slug), 1))
+        }))
+        .collect()
+}
+
+// Variables shared by every page, for the layout
+fn page_context(links: &SiteLinks, settings: &SiteSettings, feed: bool) -> Context {
+    let mut context = Context::new();
+    context.insert("site", settings);
+    context.insert("root_url", &links.root());
+    context.insert("home_url", &links.page(""));
+    if feed {
+        context.insert("feed_url", &format!("{}feed.xml", links.root()));
+    }
+    context
+}
+
+// Context of theme/article.html, base_url is used for the canonical and social urls
+pub fn article_context(
+    links: &SiteLinks,
+    settings: &SiteSettings,
+    article_id: &str,
+    cached: &CachedArticle,
+    base_url: &str,
+    feed: bool
+) -> Context {
+    let article = &cached.article;
+    // Published articles are cached with their metadata, others are parsed here
+    let (content, metadata) = match &cached.page {
+        Some(page) => (links.rebase_html(&page.html), Cow::Borrowed(&page.metadata)),
+        None => (String::new(), Cow::Owned(ArticleMetadata::from_article(article))),
+    };
+    let mut seo = ArticleSeo::new(article_id, article, &metadata, base_url);
+    seo.author = settings.author(&seo.author);
+    seo.site_name = Some(settings.name.to_owned());
+
+    let mut context = page_context(links, settings, feed);
+    context.insert("seo_tags", &seo.render_head());
+    context.insert("display_date", &settings.display_date(&article.created_date));
+    context.insert("display_author", &seo.author);
+    context.insert("reading_time", &metadata.reading_time.max(1));
+    context.insert("content", &content);
+    context.insert("tag_links", &tag_links(links, &article.tags));
+    context.insert("article", article);
+    context
+}
+
+// Context of theme/index.html for a page of the home or tag listing, None past the last page
+pub fn listing_context(
+    links: &SiteLinks,
+    settings: &SiteSettings,
+    articles: &[(String, ArticleMetadata)],
+    tag: Option<(&str, &str)>,
+    page: usize,
+    feed: bool
+) -> Option<Context> {
+    let page_count = page_count(articles.len(), settings);
+    let page_size = settings.posts_per_page.max(1);
+    if page == 0 || page > page_count {
+        return None;
+    }
+    let tag_slug = tag.map(|(slug, _)| slug);
+
+    let listed: Vec<ListedArticle> = articles.iter()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+        .map(|(article_id, metadata)| ListedArticle {
+            id: article_id,
+            url: links.page(&article_path(article_id)),
+            display_date: settings.display_date(&metadata.created_date),
+            display_author: settings.author(&metadata.author),
+            cover_url: metadata.cover_image.as_ref().map(|image| links.asset(image)),
+            tag_links: tag_links(links, &metadata.tags),
+            metadata
+        })
+        .collect();
+
+    let mut context = page_context(links, settings, feed);
+    context.insert("articles", &listed);
+    context.insert("tag", &tag.map(|(_, name)| name));
+    context.insert("page", &page);
+    context.insert("page_count", &page_count);
+    context.insert("prev_url", &(page > 1).then(|| links.page(&listing_path(tag_slug, page - 1))));
+    context.insert("next_url", &(page < page_count).then(|| links.page(&listing_path(tag_slug, page + 1))));
+    Some(context)
+}
+
+// RSS feed of the latest articles, links need a base url
+pub fn feed_xml(links: &SiteLinks, settings: &SiteSettings, articles: &[(String, ArticleMetadata)]) -> String {
+    let mut items = String::new();
+    for (article_id, metadata) in articles.iter().take(FEED_SIZE) {
+        let url = links.page(&article_path(article_id));
+        items.push_str(&format!(
+            "<item><title>{}</title><link>{}</link><guid>{}</guid><author>{}</author><pubDate>{}</pubDate><description>{}</description>{}</item>\n",
+            escape_html(&metadata.title),
+            escape_html(&url),
+            escape_html(&url),
+            escape_html(&settings.author(&metadata.author)),
+            metadata.created_date.to_rfc2822(),
+            escape_html(&metadata.excerpt),
+            metadata.tags.iter().map(|tag| format!("<category>{}</category>", escape_html(tag))).collect::<String>()
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\"><channel><title>{}</title><link>{}</link><description>{}</description>\n{}</channel></rss>\n",
+        escape_html(&settings.name),
+        escape_html(&links.page("")),
+        escape_html(if settings.description.is_empty() { &settings.name } else { &settings.description }),
+        items
+    )
+}
+
+// Sitemap of the listing pages and the indexable articles, links need a base url
+pub fn sitemap_xml(links: &SiteLinks, settings: &SiteSettings, articles: &[(String, ArticleMetadata)]) -> String {
+    let mut urls: Vec<(String, Option<String>)> = (1..=page_count(articles.len(), settings))
+        .map(|page| (links.page(&listing_path(None, page)), None))
+        .collect();
+    for slug in tags(articles).keys() {
+        urls.push((links.page(&listing_path(Some(slug), 1)), None));
+    }
+    for (article_id, metadata) in articles.iter().filter(|(_, metadata)| !metadata.noindex) {
+        urls.push((links.page(&article_path(article_id)), Some(metadata.update_date.to_rfc3339())));
+    }
+
+    let mut sitemap = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (url, last_modified) in urls {
+        match last_modified {
+            Some(date) => sitemap.push_str(&format!("<url><loc>{}</loc><lastmod>{}</lastmod></url>\n", escape_html(&url), date)),
+            None => sitemap.push_str(&format!("<url><loc>{}</loc></url>\n", escape_html(&url))),
+        }
+    }
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
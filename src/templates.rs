@@ -0,0 +1,90 @@
+use std::sync::RwLock;
+use actix_web::HttpResponse;
+use tera::{Context, Tera};
+
+use crate::error::AppError;
+use crate::locks::RwLockRecover;
+use crate::themes;
+
+const TEMPLATES_GLOB: &str = "assets/templates/**/*.html";
+
+// Html templates compiled once at startup, html is escaped by default.
+// Public pages use the templates of the selected theme, named "theme/..."
+pub struct Templates {
+    tera: RwLock<Tera>,
+    theme: RwLock<String>,
+    // Reload the templates from disk before every render, for template development
+    hot_reload: bool
+}
+
+impl Templates {
+    pub fn new(theme: &str, hot_reload: bool) -> Self {
+        let (theme, tera) = match Self::compile(theme) {
+            Ok(tera) => (theme.to_string(), tera),
+            Err(error) => {
+                log::warn!("Failed to compile theme {}, using the default theme: {:?}", theme, error);
+                match Self::compile(themes::DEFAULT_THEME) {
+                    Ok(tera) => (themes::DEFAULT_THEME.to_string(), tera),
+                    Err(error) => panic!("Failed to compile templates: {:?}", error),
+                }
+            },
+        };
+        if hot_reload {
+            log::info!("Templates hot reload enabled");
+        }
+        Self {
+            tera: RwLock::new(tera),
+            theme: RwLock::new(theme),
+            hot_reload
+        }
+    }
+
+    // Hot reload is enabled with RPUBLISH_TEMPLATES_RELOAD=1
+    pub fn from_env(theme: &str) -> Self {
+        let hot_reload = std::env::var("RPUBLISH_TEMPLATES_RELOAD").map(|value| value == "1").unwrap_or(false);
+        Self::new(theme, hot_reload)
+    }
+
+    fn compile(theme: &str) -> tera::Result<Tera> {
+        if themes::read_manifest(theme).is_none() {
+            return Err(tera::Error::msg(format!("Theme {} not found or missing theme.json", theme)));
+        }
+        let mut tera = Tera::new(TEMPLATES_GLOB)?;
+        tera.add_template_files(themes::template_files(theme))?;
+        Ok(tera)
+    }
+
+    pub fn theme(&self) -> String {
+        self.theme.read_or_recover().to_owned()
+    }
+
+    // Switch the public site theme, the current one is kept if the new one fails to compile
+    pub fn set_theme(&self, theme: &str) -> tera::Result<()> {
+        let tera = Self::compile(theme)?;
+        *self.tera.write_or_recover() = tera;
+        *self.theme.write_or_recover() = theme.to_string();
+        Ok(())
+    }
+
+    pub fn render(&self, template_name: &str, context: &Context) -> tera::Result<String> {
+        if self.hot_reload {
+            // Keep the last working templates if the edited ones don't compile
+            match Self::compile(&self.theme()) {
+                Ok(tera) => *self.tera.write_or_recover() = tera,
+                Err(error) => log::error!("Failed to reload templates: {:?}", error),
+            }
+        }
+        self.tera.read_or_recover().render(template_name, context)
+    }
+
+    // Render a full page response, failures are logged and answered with the error page
+    pub fn page(&self, template_name: &str, context: &Context) -> HttpResponse {
+        match self.render(template_name, context) {
+            Ok(page) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(page),
+            Err(error) => {
+                log::error!("Failed to render {}: {:?}", template_name, error);
+                HttpResponse::from_error(AppError::Internal.into())
+            },
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+pub const DEFAULT_THEME: &str = "default";
+
+// The built-in theme ships with the assets, installed themes live in the data directory
+const DEFAULT_THEME_PATH: &str = "assets/themes/default";
+const THEMES_PATH: &str = "data/themes";
+
+// Contents of the theme.json file at the root of every theme
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThemeManifest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String
+}
+
+// Theme names are directory names, keep them from escaping the themes directory
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn theme_path(name: &str) -> Option<PathBuf> {
+    if name == DEFAULT_THEME {
+        Some(PathBuf::from(DEFAULT_THEME_PATH))
+    } else if is_valid_name(name) {
+        Some(Path::new(THEMES_PATH).join(name))
+    } else {
+        None
+    }
+}
+
+pub fn read_manifest(name: &str) -> Option<ThemeManifest> {
+    let manifest_path = theme_path(name)?.join("theme.json");
+    let manifest_string = fs::read_to_string(manifest_path).ok()?;
+    serde_json::from_str::<ThemeManifest>(&manifest_string).ok()
+}
+
+// Every theme with a valid manifest, the default theme first
+pub fn list_themes() -> Vec<(String, ThemeManifest)> {
+    let mut themes: Vec<(String, ThemeManifest)> = Vec::new();
+    if let Some(manifest) = read_manifest(DEFAULT_THEME) {
+        themes.push((DEFAULT_THEME.to_string(), manifest));
+    }
+    if let Ok(theme_dirs) = fs::read_dir(THEMES_PATH) {
+        let mut names: Vec<String> = theme_dirs
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+            .filter(|name| name != DEFAULT_THEME && is_valid_name(name))
+            .collect();
+        names.sort();
+        for name in names {
+            match read_manifest(&name) {
+                Some(manifest) => themes.push((name, manifest)),
+                None => log::warn!("Theme {} has no valid theme.json, ignoring it", name),
+            }
+        }
+    }
+    themes
+}
+
+// Template files of the default theme followed by the ones of the selected theme,
+// named "theme/{relative path}" so the selected theme overrides the default templates
+pub fn template_files(name: &str) -> Vec<(PathBuf, Option<String>)> {
+    let mut files: Vec<(PathBuf, Option<String>)> = Vec::new();
+    let mut themes = vec![DEFAULT_THEME];
+    if name != DEFAULT_THEME {
+        themes.push(name);
+    }
+    for theme in themes {
+        if let Some(path) = theme_path(theme) {
+            let templates_path = path.join("templates");
+            for file in walk_files(&templates_path, Some("html")) {
+                if let Ok(relative) = file.strip_prefix(&templates_path) {
+                    let template_name = format!("theme/{}", relative.to_string_lossy().replace('\\', "/"));
+                    files.push((file.to_owned(), Some(template_name)));
+                }
+            }
+        }
+    }
+    files
+}
+
+// Find a static file in the selected theme or in the default theme
+pub fn asset_path(name: &str, asset: &str) -> Option<PathBuf> {
+    let asset = Path::new(asset);
+    if !asset.components().all(|component| matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    [name, DEFAULT_THEME].iter()
+        .filter_map(|theme| theme_path(theme))
+        .map(|path| path.join("static").join(asset))
+        .find(|path| path.is_file())
+}
+
+// Static files of the default theme overridden by the ones of the selected theme,
+// with their path relative to the static directory
+pub fn static_files(name: &str) -> BTreeMap<String, PathBuf> {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut themes = vec![DEFAULT_THEME];
+    if name != DEFAULT_THEME {
+        themes.push(name);
+    }
+    for theme in themes {
+        if let Some(path) = theme_path(theme) {
+            let static_path = path.join("static");
+            for file in walk_files(&static_path, None) {
+                if let Ok(relative) = file.strip_prefix(&static_path) {
+                    files.insert(relative.to_string_lossy().replace('\\', "/"), file.to_owned());
+                }
+            }
+        }
+    }
+    files
+}
+
+// Files inside path and its subdirectories, only the ones with the extension if given
+pub fn walk_files(path: &Path, extension: Option<&str>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                files.extend(walk_files(&entry_path, extension));
+            } else if extension.is_none_or(|extension| entry_path.extension().is_some_and(|found| found == extension)) {
+                files.push(entry_path);
+            }
+        }
+    }
+    files
+}